@@ -10,9 +10,13 @@
  * See the License for the specific TON DEV software governing permissions and
  * limitations under the License.
  */
-use ton_abi::{Contract, json_abi::{encode_function_call, decode_function_response}};
+use std::str::FromStr;
+
+use ton_abi::{Contract, json_abi::{encode_function_call, decode_function_response}, token::Tokenizer};
 use failure::format_err;
+use serde_json::Value;
 
+use ton_block::MsgAddressInt;
 use ton_types::{BuilderData, Result, SliceData};
 use crate::keyman::Keypair;
 
@@ -36,6 +40,38 @@ pub fn build_abi_body(
     )
 }
 
+/// Signs external message body consisting of the function id and the arguments: the body is decoded
+/// with the contract ABI and encoded again with the header and the signature laid out as the ABI version
+/// requires (the destination address is signed too since ABI 2.3). Public key is added to the header
+/// if the ABI declares it and the header does not supply it.
+pub fn sign_abi_body(
+    abi_file: &str,
+    body: SliceData,
+    header: Option<&str>,
+    keypair: Keypair,
+    address: Option<&str>,
+) -> Result<SliceData> {
+    let contract = load_abi_contract(&load_abi_json_string(abi_file)?)?;
+    let function_id = body.clone().get_next_u32()?;
+    let function = contract.function_by_id(function_id, true)
+        .map_err(|e| format_err!("function with id 0x{:08x} is not found in ABI: {}", function_id, e))?;
+    let tokens = function.decode_input(body, true, false)
+        .map_err(|e| format_err!("cannot decode body as {} call: {}", function.name, e))?;
+    let mut header = match header {
+        Some(header) => serde_json::from_str(header)
+            .map_err(|e| format_err!("ABI header must be a JSON object: {}", e))?,
+        None => Value::Object(Default::default()),
+    };
+    if contract.header().iter().any(|param| param.name == "pubkey") && header.get("pubkey").is_none() {
+        header["pubkey"] = Value::String(hex::encode(keypair.public.to_bytes()));
+    }
+    let header = Tokenizer::tokenize_optional_params(contract.header(), &header)
+        .map_err(|e| format_err!("cannot parse ABI header: {}", e))?;
+    let address = address.map(MsgAddressInt::from_str).transpose()?;
+    let body = function.encode_input(&header, &tokens, false, Some(&keypair.private), address)?;
+    SliceData::load_builder(body)
+}
+
 pub fn load_abi_json_string(abi_file: &str) -> Result<String> {
     std::fs::read_to_string(abi_file)
         .map_err(|e| format_err!("unable to read ABI file {}: {}", abi_file, e))
@@ -62,3 +98,76 @@ pub fn decode_body(
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_abi::{Function, TokenValue};
+    use ton_types::{ed25519_create_private_key, ed25519_create_public_key, ed25519_verify};
+
+    const ADDRESS: &str = "0:0101010101010101010101010101010101010101010101010101010101010101";
+    const HEADER: &str = r#"{"time": 1700000000000, "expire": 1700000060}"#;
+    const PARAMS: &str = r#"{"value": 1000}"#;
+
+    fn keypair() -> Keypair {
+        let private = ed25519_create_private_key(&[7; 32]).unwrap();
+        let public = ed25519_create_public_key(&private).unwrap();
+        Keypair { private, public }
+    }
+
+    fn write_abi(version: &str) -> String {
+        let abi = serde_json::json!({
+            "ABI version": 2,
+            "version": version,
+            "header": ["pubkey", "time", "expire"],
+            "functions": [{"name": "transfer", "inputs": [{"name": "value", "type": "uint128"}], "outputs": []}],
+            "events": [],
+            "data": [],
+            "fields": []
+        });
+        let filename = std::env::temp_dir().join(format!("tvm_linker_test_abi_{}.abi.json", version));
+        std::fs::write(&filename, abi.to_string()).unwrap();
+        filename.to_string_lossy().to_string()
+    }
+
+    /// Signs the unsigned call, checks the header and the signature and returns the signed body.
+    fn sign_and_check(version: &str, address: Option<&str>) -> SliceData {
+        let abi_file = write_abi(version);
+        let unsigned = build_abi_body(&abi_file, "transfer", PARAMS, None, None, true, None).unwrap();
+        let signed = sign_abi_body(&abi_file, SliceData::load_builder(unsigned).unwrap(), Some(HEADER), keypair(), address).unwrap();
+
+        // the same body is produced by encoding the call with the header and the key at once
+        let expected = build_abi_body(&abi_file, "transfer", PARAMS, Some(HEADER), Some(keypair()), false, address.map(str::to_string)).unwrap();
+        assert_eq!(signed.clone().into_cell(), expected.into_cell().unwrap());
+
+        let contract = load_abi_contract(&load_abi_json_string(&abi_file).unwrap()).unwrap();
+        let decoded = contract.decode_input(signed.clone(), false, false).unwrap();
+        assert_eq!(decoded.function_name, "transfer");
+        assert_eq!(decoded.tokens[0].value, TokenValue::Uint(ton_abi::Uint::new(1000, 128)));
+        let (header, _, _) = Function::decode_header(contract.version(), signed.clone(), contract.header(), false).unwrap();
+        let value = |name: &str| header.iter().find(|token| token.name == name).unwrap().value.clone();
+        assert_eq!(value("time"), TokenValue::Time(1700000000000));
+        assert_eq!(value("expire"), TokenValue::Expire(1700000060));
+        assert_eq!(value("pubkey"), TokenValue::PublicKey(Some(keypair().public)));
+
+        let (signature, hash) = contract.get_signature_data(signed.clone(), address.map(str::to_string)).unwrap();
+        ed25519_verify(&keypair().public.to_bytes(), &hash, &signature).unwrap();
+        signed
+    }
+
+    #[test]
+    fn test_sign_abi_body() {
+        sign_and_check("2.0", None);
+        sign_and_check("2.2", None);
+    }
+
+    #[test]
+    fn test_sign_abi_body_with_address() {
+        let signed = sign_and_check("2.3", Some(ADDRESS));
+        // the address is a part of the signed data
+        let contract = load_abi_contract(&load_abi_json_string(&write_abi("2.3")).unwrap()).unwrap();
+        let other = "0:0202020202020202020202020202020202020202020202020202020202020202".to_string();
+        let (signature, hash) = contract.get_signature_data(signed, Some(other)).unwrap();
+        assert!(ed25519_verify(&keypair().public.to_bytes(), &hash, &signature).is_err());
+    }
+}
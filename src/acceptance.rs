@@ -0,0 +1,139 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use serde::Serialize;
+use ton_labs_assembler::DbgPos;
+use ton_vm::executor::{Engine, EngineTraceInfo, EngineTraceInfoType};
+
+use crate::testcall::TraceHook;
+
+/// Instructions which set the gas limit and thus accept the external message.
+const ACCEPT_INSNS: [&str; 3] = ["ACCEPT", "SETGASLIMIT", "BUYGAS"];
+const CHKSIGN_INSNS: [&str; 2] = ["CHKSIGNU", "CHKSIGNS"];
+
+#[derive(Clone, Default, Serialize)]
+pub struct AcceptanceReport {
+    pub accepted: bool,
+    /// Step number of the instruction accepting the message
+    pub step: Option<u32>,
+    /// Source position of the instruction accepting the message
+    pub position: Option<String>,
+    /// Gas spent before the message was accepted, covered by the gas credit
+    pub gas_before_accept: Option<i64>,
+    pub gas_credit: i64,
+    /// Signature was checked before the message was accepted
+    pub signature_checked: bool,
+    pub warnings: Vec<String>,
+}
+
+/// Watches for the instructions accepting external inbound message.
+#[derive(Default)]
+pub struct AcceptanceChecker {
+    report: AcceptanceReport,
+}
+
+impl AcceptanceChecker {
+    pub fn new(gas_credit: i64) -> Self {
+        Self { report: AcceptanceReport { gas_credit, ..Default::default() } }
+    }
+
+    fn record(&mut self, name: &str, step: u32, gas_used: i64, position: Option<String>) {
+        if self.report.accepted {
+            return
+        }
+        if CHKSIGN_INSNS.contains(&name) {
+            self.report.signature_checked = true;
+        } else if ACCEPT_INSNS.contains(&name) {
+            self.report.accepted = true;
+            self.report.step = Some(step);
+            self.report.position = position;
+            self.report.gas_before_accept = Some(gas_used);
+        }
+    }
+
+    /// Finishes the check: `accepted` is the final state of the gas credit after the execution.
+    pub fn report(&self, accepted: bool) -> AcceptanceReport {
+        let mut report = self.report.clone();
+        report.accepted |= accepted;
+        if !report.accepted {
+            report.warnings.push("message is not accepted within the gas credit, no transaction is created".to_string());
+        } else if !report.signature_checked {
+            report.warnings.push(
+                "message is accepted without signature check: anyone can drain the balance with external messages".to_string()
+            );
+        }
+        if let Some(gas) = report.gas_before_accept {
+            if gas * 10 > report.gas_credit * 8 {
+                report.warnings.push(format!("{} of {} gas credit is spent before accept", gas, report.gas_credit));
+            }
+        }
+        report
+    }
+}
+
+impl TraceHook for AcceptanceChecker {
+    fn on_step(&mut self, _engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        if info.info_type != EngineTraceInfoType::Normal {
+            return
+        }
+        let name = info.cmd_str.split_whitespace().next().unwrap_or_default();
+        let position = position.map(|pos| format!("{}:{}", pos.filename, pos.line));
+        self.record(name, info.step, info.gas_used, position);
+    }
+}
+
+pub fn acceptance_printer(report: &AcceptanceReport) -> String {
+    let mut result = match (report.accepted, report.step) {
+        (true, Some(step)) => format!("External message accepted at step {}", step),
+        (true, None) => "External message accepted".to_string(),
+        (false, _) => "External message not accepted".to_string(),
+    };
+    if let Some(position) = &report.position {
+        result += &format!(" ({})", position);
+    }
+    if let Some(gas) = report.gas_before_accept {
+        result += &format!(", gas before accept: {} of {} credit", gas, report.gas_credit);
+    }
+    result += &format!("\nSignature checked before accept: {}\n", report.signature_checked);
+    for warning in &report.warnings {
+        result += &format!("WARNING: {}\n", warning);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_without_signature() {
+        let mut checker = AcceptanceChecker::new(10000);
+        checker.record("PUSHINT", 1, 18, None);
+        checker.record("ACCEPT", 2, 44, Some("Wallet.sol:10".to_string()));
+        checker.record("CHKSIGNU", 3, 70, None);
+        let report = checker.report(true);
+        assert_eq!(report.step, Some(2));
+        assert_eq!(report.gas_before_accept, Some(44));
+        assert!(!report.signature_checked);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_not_accepted() {
+        let mut checker = AcceptanceChecker::new(10000);
+        checker.record("CHKSIGNU", 1, 9000, None);
+        let report = checker.report(false);
+        assert!(!report.accepted);
+        assert!(report.signature_checked);
+        assert_eq!(report.warnings.len(), 1);
+    }
+}
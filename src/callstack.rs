@@ -0,0 +1,187 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use ton_labs_assembler::DbgPos;
+use ton_types::UInt256;
+use ton_vm::{
+    executor::{Engine, EngineTraceInfo},
+    stack::StackItem,
+};
+
+const ROOT_FRAME: &str = "main";
+
+/// Code location of the instruction: cell hash and offset in bits.
+pub type CodePoint = (UInt256, usize);
+
+pub fn code_point(info: &EngineTraceInfo) -> CodePoint {
+    (info.cmd_code.cell().repr_hash(), info.cmd_code.pos())
+}
+
+fn is_call(cmd_str: &str) -> bool {
+    let name = cmd_str.split_whitespace().next().unwrap_or_default();
+    name.starts_with("CALL") && !name.starts_with("CALLCC")
+}
+
+/// Jumps which do not return, i.e. tail calls.
+fn is_jump(cmd_str: &str) -> bool {
+    matches!(cmd_str.split_whitespace().next().unwrap_or_default(), "JMPX" | "JMPREF" | "JMPDICT" | "JMPXDATA" | "JMPREFDATA")
+}
+
+/// Code point of the instruction with its source position, formatted only when printed.
+#[derive(Clone, PartialEq)]
+pub struct Location {
+    pub point: CodePoint,
+    pub position: Option<DbgPos>,
+}
+
+impl Location {
+    fn new(info: &EngineTraceInfo, position: Option<&DbgPos>) -> Self {
+        Self { point: code_point(info), position: position.cloned() }
+    }
+
+    fn update(&mut self, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        self.point = code_point(info);
+        if self.position.as_ref() != position {
+            self.position = position.cloned();
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.position {
+            Some(pos) => write!(f, "{}:{}", pos.filename, pos.line),
+            None => write!(f, "{}:{}", &self.point.0.to_hex_string()[..16], self.point.1),
+        }
+    }
+}
+
+struct Frame {
+    /// The first instruction of the function, `None` until the function is entered
+    function: Option<Location>,
+    return_point: Option<CodePoint>,
+    /// The current instruction of the frame: the call site for the outer frames
+    position: Option<Location>,
+}
+
+/// Shadow call stack restored from the executed instructions.
+/// A frame is pushed after a CALL-like instruction and popped when the execution
+/// reaches the return continuation saved in c0 by that call, a jump replaces
+/// the function of the frame (except the outermost one).
+/// Functions are named by the location of their first instruction.
+pub struct CallStack {
+    frames: Vec<Frame>,
+    entered: bool,
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        Self {
+            frames: vec![Frame { function: None, return_point: None, position: None }],
+            entered: false,
+        }
+    }
+}
+
+impl CallStack {
+    /// Updates the stack with the instruction being traced, must be called for every step.
+    pub fn update(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        let point = code_point(info);
+        if self.entered {
+            self.entered = false;
+            if let Some(frame) = self.frames.last_mut() {
+                frame.function = Some(Location::new(info, position));
+            }
+        } else if let Some(depth) = self.frames.iter().rposition(|frame| frame.return_point.as_ref() == Some(&point)) {
+            self.frames.truncate(depth);
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            match &mut frame.position {
+                Some(location) => location.update(info, position),
+                None => frame.position = Some(Location::new(info, position)),
+            }
+        }
+        if is_call(&info.cmd_str) {
+            let return_point = match engine.ctrl(0) {
+                Ok(StackItem::Continuation(cont)) => Some((cont.code().cell().repr_hash(), cont.code().pos())),
+                _ => None,
+            };
+            self.frames.push(Frame { function: None, return_point, position: None });
+            self.entered = true;
+        } else if is_jump(&info.cmd_str) && self.frames.len() > 1 {
+            self.entered = true;
+        }
+    }
+
+    /// The outermost frame is the entry point, the others are named by the location of the function.
+    fn frame_name(depth: usize, frame: &Frame) -> Option<String> {
+        match depth {
+            0 => Some(ROOT_FRAME.to_string()),
+            _ => frame.function.as_ref().map(ToString::to_string),
+        }
+    }
+
+    /// Frames from the innermost to the outermost one: the current location and the function.
+    pub fn backtrace(&self) -> Vec<String> {
+        self.frames.iter().enumerate().rev()
+            .filter_map(|(depth, frame)| Some((Self::frame_name(depth, frame)?, frame.position.as_ref()?)))
+            .enumerate()
+            .map(|(i, (name, position))| format!("#{} {} in {}", i, position, name))
+            .collect()
+    }
+
+    /// Location of the current instruction.
+    pub fn position(&self) -> Option<String> {
+        self.frames.iter().rev().find_map(|frame| frame.position.as_ref()).map(ToString::to_string)
+    }
+
+    /// Entry points of the functions from the outermost to the innermost one,
+    /// `None` stands for the outermost frame.
+    pub fn functions(&self) -> impl Iterator<Item = Option<&CodePoint>> {
+        self.frames.iter().enumerate().filter_map(|(depth, frame)| match depth {
+            0 => Some(None),
+            _ => frame.function.as_ref().map(|function| Some(&function.point)),
+        })
+    }
+
+    /// Name of the function: `main` for the outermost frame, otherwise the location of the entry point.
+    pub fn function_name(function: Option<&CodePoint>, position: Option<&DbgPos>) -> String {
+        match function {
+            None => ROOT_FRAME.to_string(),
+            Some(point) => Location { point: point.clone(), position: position.cloned() }.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backtrace() {
+        let location = |line: usize| Some(Location {
+            point: (UInt256::default(), line),
+            position: Some(DbgPos { filename: "a.sol".to_string(), line }),
+        });
+        let mut call_stack = CallStack::default();
+        call_stack.frames[0].position = location(5);
+        call_stack.frames.push(Frame { function: location(10), return_point: None, position: location(12) });
+        call_stack.frames.push(Frame { function: None, return_point: None, position: None });
+        assert_eq!(call_stack.backtrace(), vec!["#0 a.sol:12 in a.sol:10".to_string(), "#1 a.sol:5 in main".to_string()]);
+        let functions: Vec<_> = call_stack.functions().collect();
+        assert_eq!(functions, vec![None, Some(&(UInt256::default(), 10))]);
+        assert_eq!(call_stack.position().as_deref(), Some("a.sol:12"));
+        let unmapped = Location { point: (UInt256::default(), 7), position: None };
+        assert_eq!(unmapped.to_string(), "0000000000000000:7");
+        assert!(is_jump("JMPREF") && !is_jump("JMPXARGS 1"));
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use failure::{bail, format_err};
+use ton_block::GlobalCapabilities;
+use ton_types::Result;
+
+/// Builds the list of capability names with their masks taken from `GlobalCapabilities`.
+macro_rules! capabilities {
+    ($($cap:ident),* $(,)?) => {
+        [$((stringify!($cap), GlobalCapabilities::$cap as u64)),*]
+    };
+}
+
+/// Capability flags of `ton_block::GlobalCapabilities` by name.
+pub const CAPABILITIES: [(&str, u64); 32] = capabilities![
+    CapIhrEnabled, CapCreateStatsEnabled, CapBounceMsgBody, CapReportVersion, CapSplitMergeTransactions,
+    CapShortDequeue, CapMbppEnabled, CapFastStorageStat, CapInitCodeHash, CapOffHypercube, CapMycode, CapSetLibCode,
+    CapFixTupleIndexBug, CapRemp, CapDelections, CapFullBodyInBounced, CapStorageFeeToTvm, CapCopyleft,
+    CapIndexAccounts, CapDiff, CapsTvmBugfixes2022, CapWorkchains, CapStcontNewFormat, CapFastStorageStatBugfix,
+    CapResolveMerkleCell, CapSignatureWithId, CapBounceAfterFailedAction, CapGroth16, CapFeeInGasUnits, CapBigCells,
+    CapSuspendedList, CapFastFinality,
+];
+
+/// Parses capability flag: its name (case insensitive, `Cap` prefix can be omitted),
+/// decimal or `0x`-prefixed hex mask.
+pub fn parse_capability(value: &str) -> Result<u64> {
+    if let Some(hex) = value.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16).map_err(|e| format_err!("invalid capabilities {}: {}", value, e))
+    }
+    if let Ok(mask) = value.parse::<u64>() {
+        return Ok(mask)
+    }
+    let name = value.to_lowercase();
+    CAPABILITIES.iter()
+        .find(|(cap, _)| {
+            let cap = cap.to_lowercase();
+            cap == name || cap.strip_prefix("cap") == Some(name.as_str())
+        })
+        .map(|(_, mask)| *mask)
+        .ok_or_else(|| format_err!("unknown capability {}", value))
+}
+
+/// Applies comma separated list of changes to the capabilities: `+Flag` or `Flag` enables
+/// and `-Flag` disables the flag. A single number replaces the capabilities.
+pub fn apply_capabilities(capabilities: u64, spec: &str) -> Result<u64> {
+    let spec = spec.trim();
+    if spec.starts_with("0x") || spec.parse::<u64>().is_ok() {
+        return parse_capability(spec)
+    }
+    let mut capabilities = capabilities;
+    for item in spec.split(',').map(str::trim) {
+        if let Some(flag) = item.strip_prefix('-') {
+            capabilities &= !parse_capability(flag)?;
+        } else if let Some(flag) = item.strip_prefix('+') {
+            capabilities |= parse_capability(flag)?;
+        } else if !item.is_empty() {
+            capabilities |= parse_capability(item)?;
+        } else {
+            bail!("empty capability in {}", spec)
+        }
+    }
+    Ok(capabilities)
+}
+
+/// Returns names of the enabled flags, unknown bits are returned as a hex mask.
+pub fn capability_names(capabilities: u64) -> Vec<String> {
+    let mut names = vec![];
+    let mut known = 0;
+    for (name, mask) in CAPABILITIES {
+        known |= mask;
+        if capabilities & mask != 0 {
+            names.push(name.to_string());
+        }
+    }
+    if capabilities & !known != 0 {
+        names.push(format!("0x{:x}", capabilities & !known));
+    }
+    names
+}
+
+pub fn capabilities_printer(capabilities: u64) -> String {
+    format!("0x{:x} ({})", capabilities, capability_names(capabilities).join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testcall::DEFAULT_CAPABILITIES;
+
+    #[test]
+    fn test_apply_capabilities() {
+        let caps = apply_capabilities(DEFAULT_CAPABILITIES, "+CapInitCodeHash, -Mycode").unwrap();
+        assert_eq!(caps, (DEFAULT_CAPABILITIES | 0x100) & !0x400);
+        assert_eq!(apply_capabilities(DEFAULT_CAPABILITIES, "0x400").unwrap(), 0x400);
+        assert_eq!(apply_capabilities(0, "capcopyleft").unwrap(), 0x4_0000);
+        assert!(apply_capabilities(0, "+CapUnknown").is_err());
+        assert!(apply_capabilities(0, "+CapMycode,,").is_err());
+    }
+
+    #[test]
+    fn test_capability_names() {
+        assert_eq!(capability_names(0x400 | 0x8_0000_0000), vec!["CapMycode".to_string(), "0x800000000".to_string()]);
+        assert!(capabilities_printer(DEFAULT_CAPABILITIES).starts_with("0x880116ae (CapCreateStatsEnabled, CapBounceMsgBody,"));
+    }
+}
@@ -0,0 +1,394 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::{collections::{BTreeMap, HashMap}, path::Path};
+
+use failure::{bail, format_err};
+use serde_json::Value;
+use ton_block::{Serializable, StateInit};
+use ton_labs_assembler::{compile_code_debuggable, DbgInfo, Line, Lines};
+use ton_types::{BuilderData, Cell, HashmapE, HashmapType, IBitstring, Result, SliceData, Status};
+
+use crate::abi::{load_abi_contract, load_abi_json_string};
+
+pub const LIB_PATH_ENV: &str = "TVM_LINKER_LIB_PATH";
+const SETCP0: u64 = 0xFF00;
+const DICTPUSHCONST: u64 = 0x3D29; // F4A6_ without completion tag, followed by 10 bits of key length
+const DICTIGETJMPZ: u64 = 0xF4BC;
+const THROWARG_11: u64 = 0xF2C80B;
+const JMPREF: u64 = 0xDB3D;
+const FUNCTION_ID_BITS: usize = 32;
+/// Directives describing the object file layout, they do not affect the code.
+const LAYOUT_DIRECTIVES: [&str; 6] = [".size", ".p2align", ".file", ".ident", ".text", ".section"];
+
+/// Returns ABI file for the source: either supplied one or `<source>.abi.json` if it exists.
+pub fn resolve_abi_file(source: &str, abi_file: Option<&str>) -> Option<String> {
+    if let Some(abi_file) = abi_file {
+        return Some(abi_file.to_string())
+    }
+    let stem = Path::new(source).with_extension("");
+    let abi_file = format!("{}.abi.json", stem.display());
+    if Path::new(&abi_file).exists() {
+        Some(abi_file)
+    } else {
+        None
+    }
+}
+
+/// Returns runtime libraries: either supplied ones or the one defined by `TVM_LINKER_LIB_PATH`.
+pub fn resolve_libs(libs: Vec<String>) -> Vec<String> {
+    if !libs.is_empty() {
+        return libs
+    }
+    std::env::var(LIB_PATH_ENV).map(|path| vec![path]).unwrap_or_default()
+}
+
+/// Function defined in the source: its declarations and body.
+#[derive(Default)]
+struct Function {
+    public: bool,
+    internal: bool,
+    defined: bool,
+    lines: Lines,
+}
+
+/// Functions of the linked source in the order of their declarations.
+#[derive(Default)]
+struct Program {
+    names: Vec<String>,
+    functions: HashMap<String, Function>,
+    /// Selectors of the entry points by `.internal-alias`
+    aliases: HashMap<String, i32>,
+}
+
+impl Program {
+    fn declare(&mut self, name: &str) -> &mut Function {
+        if !self.functions.contains_key(name) {
+            self.names.push(name.to_string());
+        }
+        self.functions.entry(name.to_string()).or_default()
+    }
+}
+
+fn location(line: &Line) -> String {
+    format!("{}:{}", line.pos.filename, line.pos.line)
+}
+
+/// Splits `.directive arg1, arg2` into the directive and its arguments, `:` prefixes of the names are dropped.
+fn parse_directive(code: &str) -> (&str, Vec<&str>) {
+    let (directive, args) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+    let args = args.split(',')
+        .map(|arg| arg.trim().trim_start_matches(':'))
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    (directive, args)
+}
+
+/// Collects function declarations and splits the source into function bodies:
+/// `.globl`/`.type name, @function` declare a function, `.public` marks it as an ABI function,
+/// `.internal :name` with `.internal-alias :name, id` makes it an entry point with the given selector,
+/// a `name:` label starts the body of the declared function.
+fn parse(lines: Lines) -> Result<Program> {
+    let mut program = Program::default();
+    let mut current: Option<String> = None;
+    for line in lines {
+        let code = line.text.split(';').next().unwrap_or_default().trim();
+        if code.is_empty() {
+            continue
+        }
+        if let Some(label) = code.strip_suffix(':').filter(|label| !label.contains(char::is_whitespace)) {
+            let function = program.functions.get_mut(label)
+                .ok_or_else(|| format_err!("{}: label {} is not declared as a function", location(&line), label))?;
+            if function.defined {
+                bail!("{}: function {} is defined twice", location(&line), label)
+            }
+            function.defined = true;
+            current = Some(label.to_string());
+            continue
+        }
+        let (directive, args) = parse_directive(code);
+        match (directive, args.as_slice()) {
+            (".globl", [name]) | (".type", [name, "@function"]) => {
+                program.declare(name);
+            }
+            (".public", [name]) => program.declare(name).public = true,
+            (".internal", [name]) => program.declare(name).internal = true,
+            (".internal-alias", [name, id]) => {
+                let id = id.parse::<i32>()
+                    .map_err(|e| format_err!("{}: invalid internal id {}: {}", location(&line), id, e))?;
+                program.aliases.insert(name.to_string(), id);
+            }
+            (".globl" | ".type" | ".public" | ".internal" | ".internal-alias", _) => {
+                bail!("{}: invalid directive {}", location(&line), code)
+            }
+            _ if LAYOUT_DIRECTIVES.contains(&directive) => (),
+            _ => match &current {
+                Some(name) => program.functions.get_mut(name).unwrap().lines.push(line),
+                None => bail!("{}: instruction outside of a function: {}", location(&line), code),
+            }
+        }
+    }
+    if let Some(name) = program.names.iter().find(|name| !program.functions[*name].defined) {
+        bail!("function {} is declared but not defined", name)
+    }
+    Ok(program)
+}
+
+/// Assigns function ids: ABI function ids for the public functions, internal aliases
+/// for the entry points and sequential ids starting from 1 for the other functions,
+/// so they can be called with `CALLDICT $name$`.
+fn function_ids(program: &Program, abi_file: Option<&str>) -> Result<BTreeMap<i32, String>> {
+    let contract = abi_file
+        .map(|abi_file| load_abi_contract(&load_abi_json_string(abi_file)?))
+        .transpose()?;
+    let mut ids = BTreeMap::new();
+    let mut private = vec![];
+    for name in &program.names {
+        let function = &program.functions[name];
+        let id = match (function.internal, function.public) {
+            (true, _) => *program.aliases.get(name)
+                .ok_or_else(|| format_err!("internal function {} has no .internal-alias", name))?,
+            (false, true) => {
+                let contract = contract.as_ref()
+                    .ok_or_else(|| format_err!("public function {} requires the contract ABI", name))?;
+                let function = contract.function(name)
+                    .map_err(|_| format_err!("public function {} is not found in the ABI", name))?;
+                function.get_input_id() as i32
+            }
+            (false, false) => {
+                private.push(name);
+                continue
+            }
+        };
+        if let Some(other) = ids.insert(id, name.clone()) {
+            bail!("functions {} and {} have the same id {}", other, name, id)
+        }
+    }
+    let mut next_id = 1;
+    for name in private {
+        while ids.contains_key(&next_id) {
+            next_id += 1;
+        }
+        ids.insert(next_id, name.clone());
+    }
+    Ok(ids)
+}
+
+/// Replaces `$name$` placeholders with the ids of the functions.
+fn substitute_function_ids(lines: &mut Lines, ids: &BTreeMap<i32, String>) {
+    for line in lines {
+        for (id, name) in ids {
+            let placeholder = format!("${}$", name);
+            if line.text.contains(&placeholder) {
+                line.text = line.text.replace(&placeholder, &id.to_string());
+            }
+        }
+    }
+}
+
+fn function_key(id: i32) -> Result<SliceData> {
+    let mut key = BuilderData::new();
+    key.append_i32(id)?;
+    SliceData::load_builder(key)
+}
+
+/// Merges debug maps of the separately assembled functions.
+fn merge_debug_info(parts: Vec<DbgInfo>) -> Result<DbgInfo> {
+    let mut merged = serde_json::Map::new();
+    for part in parts {
+        match serde_json::to_value(part)? {
+            Value::Object(map) => merged.extend(map),
+            _ => bail!("debug map is not a JSON object"),
+        }
+    }
+    Ok(serde_json::from_value(Value::Object(merged))?)
+}
+
+/// Creates the root cell: `SETCP0; <functions> 32 DICTPUSHCONST; DICTIGETJMPZ; 11 THROWARG`.
+/// The selector is taken from the stack, so functions are called by id with c3 as well.
+fn selector(functions: &HashmapE) -> Result<Cell> {
+    let dict = functions.data().ok_or_else(|| format_err!("no functions are defined"))?;
+    let mut root = BuilderData::new();
+    root.append_bits(SETCP0 as usize, 16)?;
+    root.append_bits(DICTPUSHCONST as usize, 14)?;
+    root.append_bits(FUNCTION_ID_BITS, 10)?;
+    root.checked_append_reference(dict.clone())?;
+    root.append_bits(DICTIGETJMPZ as usize, 16)?;
+    root.append_bits(THROWARG_11 as usize, 24)?;
+    root.into_cell()
+}
+
+fn load_lines(filename: &str) -> Result<Lines> {
+    let text = std::fs::read_to_string(filename)
+        .map_err(|e| format_err!("unable to read source file {}: {}", filename, e))?;
+    Ok(text.lines()
+        .enumerate()
+        .map(|(i, line)| Line::new(&format!("{}\n", line), filename, i + 1))
+        .collect())
+}
+
+/// Links runtime libraries with the source, assembles every function and puts them
+/// into the selector dictionary by their ids.
+pub fn compile(source: &str, libs: &[String], abi_file: Option<&str>, debug: bool) -> Result<(Cell, DbgInfo)> {
+    let mut lines = Lines::new();
+    for lib in libs {
+        lines.append(&mut load_lines(lib)?);
+    }
+    lines.append(&mut load_lines(source)?);
+    let mut program = parse(lines)?;
+    let ids = function_ids(&program, abi_file)?;
+    let mut functions = HashmapE::with_bit_len(FUNCTION_ID_BITS);
+    let mut dbg_info = vec![];
+    for (id, name) in &ids {
+        let mut lines = std::mem::take(&mut program.functions.get_mut(name).unwrap().lines);
+        substitute_function_ids(&mut lines, &ids);
+        if debug {
+            println!("{} (id {}):", name, id);
+            for line in &lines {
+                print!("{}", line.text);
+            }
+        }
+        let (code, info) = compile_code_debuggable(lines)
+            .map_err(|e| format_err!("compilation of function {} failed: {}", name, e))?;
+        let mut stub = BuilderData::new();
+        stub.append_bits(JMPREF as usize, 16)?;
+        stub.checked_append_reference(code.into_cell())?;
+        functions.set_builder(function_key(*id)?, &stub)?;
+        dbg_info.push(info);
+    }
+    Ok((selector(&functions)?, merge_debug_info(dbg_info)?))
+}
+
+/// Creates initial persistent data: dictionary with zero public key at key 0.
+pub fn initial_data() -> Result<Cell> {
+    let mut dict = HashmapE::with_bit_len(64);
+    let mut key = BuilderData::new();
+    key.append_u64(0)?;
+    let mut pubkey = BuilderData::new();
+    pubkey.append_raw(&[0u8; 32], 256)?;
+    dict.set_builder(SliceData::load_builder(key)?, &pubkey)?;
+    let mut data = BuilderData::new();
+    dict.write_to(&mut data)?;
+    data.into_cell()
+}
+
+pub fn save_debug_map(dbg_info: &DbgInfo, filename: &str) -> Status {
+    let json = serde_json::to_string_pretty(dbg_info)
+        .map_err(|e| format_err!("failed to serialize debug map: {}", e))?;
+    std::fs::write(filename, json)
+        .map_err(|e| format_err!("failed to write debug map {}: {}", filename, e))
+}
+
+pub fn create_state_init(code: Cell) -> Result<StateInit> {
+    if code.bit_length() == 0 && code.references_count() == 0 {
+        bail!("code cell is empty")
+    }
+    Ok(StateInit {
+        code: Some(code),
+        data: Some(initial_data()?),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_vm::{
+        error::tvm_exception,
+        executor::{gas::gas_state::Gas, Engine},
+        int,
+        stack::{Stack, StackItem},
+    };
+
+    const SOURCE: &str = "
+	.internal-alias :main_internal, 0
+	.internal :main_internal
+	.globl main_internal
+	.type main_internal, @function
+main_internal:
+	; reads the function id from the body and calls it through the selector
+	LDU 32
+	SWAP
+	PUSHCTR c3
+	EXECUTE
+
+	.globl store
+	.public store
+	.type store, @function
+store:
+	LDU 32
+	ENDS
+	CALLDICT $double$
+	NEWC
+	STU 32
+	ENDC
+	POPROOT
+	.size store, .-store
+
+	.type double, @function
+double:
+	DUP
+	ADD
+";
+
+    const ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": [],
+        "functions": [{"name": "store", "inputs": [{"name": "value", "type": "uint32"}], "outputs": []}],
+        "events": [],
+        "data": [],
+        "fields": []
+    }"#;
+
+    fn run(code: Cell, body: SliceData, selector: i32) -> (i32, Option<Cell>) {
+        let mut stack = Stack::new();
+        stack.push(StackItem::Slice(body)).push(int!(selector));
+        let mut engine = Engine::with_capabilities(0)
+            .setup_with_libraries(SliceData::load_cell(code).unwrap(), None, Some(stack), Some(Gas::test()), vec![]);
+        let exit_code = match engine.execute() {
+            Ok(code) => code,
+            Err(err) => tvm_exception(err).unwrap().exception_or_custom_code(),
+        };
+        let data = match engine.get_committed_state().get_root() {
+            StackItem::Cell(cell) => Some(cell.clone()),
+            _ => None,
+        };
+        (exit_code, data)
+    }
+
+    #[test]
+    fn test_compile_and_run() {
+        let dir = std::env::temp_dir().join("tvm_linker_test_compile");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("contract.code").to_string_lossy().to_string();
+        let abi_file = dir.join("contract.abi.json").to_string_lossy().to_string();
+        std::fs::write(&source, SOURCE).unwrap();
+        std::fs::write(&abi_file, ABI).unwrap();
+        assert_eq!(resolve_abi_file(&source, None).as_deref(), Some(abi_file.as_str()));
+
+        let (code, dbg_info) = compile(&source, &[], Some(&abi_file), false).unwrap();
+        let store_id = load_abi_contract(ABI).unwrap().function("store").unwrap().get_input_id();
+        let mut body = BuilderData::new();
+        body.append_u32(store_id).unwrap();
+        body.append_u32(21).unwrap();
+        let (exit_code, data) = run(code.clone(), SliceData::load_builder(body).unwrap(), 0);
+        assert_eq!(exit_code, 0);
+        assert_eq!(SliceData::load_cell(data.unwrap()).unwrap().get_next_u32().unwrap(), 42);
+        // unknown selector
+        assert_eq!(run(code, SliceData::default(), -1).0, 11);
+        assert!(serde_json::to_string(&dbg_info).unwrap().contains("contract.code"));
+
+        let err = compile(&source, &[], None, false).unwrap_err();
+        assert_eq!(err.to_string(), "public function store requires the contract ABI");
+    }
+}
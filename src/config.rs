@@ -0,0 +1,223 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::{bail, format_err};
+use serde_json::{Map, Value};
+use ton_block::{ConfigParam8, ConfigParamEnum, ConfigParams, StateInit};
+use ton_types::{BuilderData, Cell, HashmapType, IBitstring, Result, SliceData, Status, UInt256};
+
+use crate::capabilities::apply_capabilities;
+use crate::program::{load_from_file, save_to_file};
+use crate::testcall::{config_params, load_config};
+
+/// Changes applied to the base config.
+#[derive(Default)]
+pub struct ConfigPatch {
+    /// Config params in JSON, `null` value removes the param
+    pub params: Option<Map<String, Value>>,
+    /// Capabilities changes, see `apply_capabilities`
+    pub capabilities: Option<String>,
+    pub enable: u64,
+    pub disable: u64,
+}
+
+fn param_key(index: u32) -> Result<SliceData> {
+    let mut key = BuilderData::new();
+    key.append_u32(index)?;
+    SliceData::load_builder(key)
+}
+
+/// Returns hashes of the params by their indexes.
+fn param_hashes(config: &ConfigParams) -> Result<BTreeMap<u32, UInt256>> {
+    let mut hashes = BTreeMap::new();
+    config.config_params.iterate_slices(|mut key, value| {
+        let hash = value.reference(0).map(|cell| cell.repr_hash()).unwrap_or_else(|_| value.cell().repr_hash());
+        hashes.insert(key.get_next_u32()?, hash);
+        Ok(true)
+    })?;
+    Ok(hashes)
+}
+
+fn param_json(config: &ConfigParams, index: u32) -> Result<Value> {
+    let text = ton_block_json::serialize_config_param(config, index)
+        .map_err(|e| format_err!("cannot serialize config param {}: {}", index, e))?;
+    let mut value: Value = serde_json::from_str(&text)?;
+    Ok(match value.get_mut(&format!("p{}", index)) {
+        Some(param) => param.take(),
+        None => value,
+    })
+}
+
+pub fn config_to_json(config: &ConfigParams) -> Result<Value> {
+    let mut result = Map::new();
+    for index in param_hashes(config)?.keys() {
+        result.insert(format!("p{}", index), param_json(config, *index)?);
+    }
+    Ok(Value::Object(result))
+}
+
+pub fn apply_patch(config: &mut ConfigParams, patch: &ConfigPatch) -> Status {
+    if let Some(params) = &patch.params {
+        let mut changed = Map::new();
+        for (name, value) in params {
+            let index = name.strip_prefix('p').and_then(|index| index.parse::<u32>().ok())
+                .ok_or_else(|| format_err!("invalid config param name {}, must be p<index>", name))?;
+            if value.is_null() {
+                config.config_params.remove(param_key(index)?)?;
+            } else {
+                changed.insert(name.clone(), value.clone());
+            }
+        }
+        if !changed.is_empty() {
+            let patch_params = ton_block_json::parse_config(&changed)
+                .map_err(|e| format_err!("cannot parse config params patch: {}", e))?;
+            patch_params.config_params.iterate_slices(|key, value| {
+                config.config_params.set(key, &value)?;
+                Ok(true)
+            })?;
+        }
+    }
+    if patch.capabilities.is_some() || patch.enable != 0 || patch.disable != 0 {
+        let mut global_version = config.get_global_version()?;
+        let capabilities = match &patch.capabilities {
+            Some(spec) => apply_capabilities(global_version.capabilities, spec)?,
+            None => global_version.capabilities,
+        };
+        global_version.capabilities = (capabilities | patch.enable) & !patch.disable;
+        config.set_config(ConfigParamEnum::ConfigParam8(ConfigParam8 { global_version }))?;
+    }
+    Ok(())
+}
+
+/// Lists added, removed and changed params with their old and new values.
+pub fn config_diff(old: &ConfigParams, new: &ConfigParams) -> Result<String> {
+    let old_hashes = param_hashes(old)?;
+    let new_hashes = param_hashes(new)?;
+    let indexes: BTreeSet<_> = old_hashes.keys().chain(new_hashes.keys()).collect();
+    let mut result = String::new();
+    for index in indexes {
+        match (old_hashes.get(index), new_hashes.get(index)) {
+            (Some(old_hash), Some(new_hash)) if old_hash == new_hash => (),
+            (Some(_), Some(_)) => {
+                result += &format!("~ p{}:\n  - {}\n  + {}\n", index, param_json(old, *index)?, param_json(new, *index)?);
+            }
+            (None, Some(_)) => result += &format!("+ p{}: {}\n", index, param_json(new, *index)?),
+            (Some(_), None) => result += &format!("- p{}: {}\n", index, param_json(old, *index)?),
+            (None, None) => unreachable!(),
+        }
+    }
+    Ok(result)
+}
+
+/// Creates config contract state: the base state with the config dictionary replaced
+/// or the data consisting of the config dictionary, zero seqno and public key.
+fn config_state(root: Cell, base: Option<StateInit>) -> Result<StateInit> {
+    let mut state = base.unwrap_or_default();
+    let data = match &state.data {
+        Some(data) if data.references_count() != 0 => {
+            let mut builder = BuilderData::from_cell(data)?;
+            builder.replace_reference_cell(0, root);
+            builder
+        }
+        _ => {
+            let mut builder = BuilderData::new();
+            builder.checked_append_reference(root)?;
+            builder.append_u32(0)?;
+            builder.append_raw(&[0; 32], 256)?;
+            builder
+        }
+    };
+    state.data = Some(data.into_cell()?);
+    Ok(state)
+}
+
+pub fn config_command(base: &str, patch: ConfigPatch, output: Option<&str>) -> Status {
+    let (root, base_state) = if base.ends_with(".json") {
+        (load_config(base)?, None)
+    } else {
+        (load_config(base)?, Some(load_from_file(base)?))
+    };
+    let old = config_params(root.clone());
+    let mut new = config_params(root);
+    apply_patch(&mut new, &patch)?;
+    let diff = config_diff(&old, &new)?;
+    if diff.is_empty() {
+        println!("Config is not changed");
+    } else {
+        print!("{}", diff);
+    }
+    let root = match new.config_params.data() {
+        Some(root) => root.clone(),
+        None => bail!("resulting config is empty"),
+    };
+    match output {
+        Some(filename) if filename.ends_with(".json") => {
+            let json = serde_json::to_string_pretty(&config_to_json(&new)?)?;
+            std::fs::write(filename, json)
+                .map_err(|e| format_err!("failed to write config {}: {}", filename, e))?;
+            println!("Config saved to {}", filename);
+        }
+        Some(filename) => {
+            save_to_file(config_state(root, base_state)?, Some(filename), -1, true)?;
+            println!("Config saved to {}", filename);
+        }
+        None => (),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testcall::parse_config_json;
+
+    const CONFIG: &str = r#"{
+        "p8": {"version": 32, "capabilities": "2281772718"},
+        "p31": ["0000000000000000000000000000000000000000000000000000000000000000"]
+    }"#;
+
+    #[test]
+    fn test_patch_capabilities() {
+        let root = parse_config_json(CONFIG).unwrap();
+        let old = config_params(root.clone());
+        let mut new = config_params(root);
+        let patch = ConfigPatch {
+            capabilities: Some("+CapInitCodeHash".to_string()),
+            enable: 0x1,
+            disable: 0x2,
+            ..Default::default()
+        };
+        apply_patch(&mut new, &patch).unwrap();
+        assert_eq!(new.capabilities(), (old.capabilities() | 0x101) & !0x2);
+        assert!(config_diff(&old, &new).unwrap().starts_with("~ p8:"));
+        assert!(config_diff(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_remove_param() {
+        let root = parse_config_json(CONFIG).unwrap();
+        let mut config = config_params(root);
+        let mut params = Map::new();
+        params.insert("p31".to_string(), Value::Null);
+        apply_patch(&mut config, &ConfigPatch { params: Some(params), ..Default::default() }).unwrap();
+        assert!(!param_hashes(&config).unwrap().contains_key(&31));
+    }
+
+    #[test]
+    fn test_config_state() {
+        let root = parse_config_json(CONFIG).unwrap();
+        let state = config_state(root.clone(), None).unwrap();
+        assert_eq!(state.data.unwrap().reference(0).unwrap(), root);
+    }
+}
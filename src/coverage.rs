@@ -0,0 +1,130 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::{collections::BTreeMap, path::Path};
+
+use failure::format_err;
+use ton_labs_assembler::{DbgInfo, DbgPos};
+use ton_types::{Result, Status};
+use ton_vm::executor::{Engine, EngineTraceInfo, EngineTraceInfoType};
+
+use crate::testcall::TraceHook;
+
+/// Execution counts of source lines: filename -> line -> hits.
+#[derive(Default)]
+pub struct Coverage {
+    files: BTreeMap<String, BTreeMap<usize, u64>>,
+}
+
+impl Coverage {
+    /// Registers all the lines mentioned in the debug map so that
+    /// never executed lines are reported with zero hits.
+    pub fn with_debug_info(debug_info: Option<&DbgInfo>) -> Result<Self> {
+        let mut coverage = Self::default();
+        if let Some(debug_info) = debug_info {
+            let map: BTreeMap<String, BTreeMap<String, DbgPos>> = serde_json::from_value(serde_json::to_value(debug_info)?)
+                .map_err(|e| format_err!("unexpected debug map format: {}", e))?;
+            for pos in map.values().flat_map(BTreeMap::values) {
+                coverage.add(&pos.filename, pos.line, 0);
+            }
+        }
+        Ok(coverage)
+    }
+
+    fn add(&mut self, filename: &str, line: usize, hits: u64) {
+        if filename.is_empty() || line == 0 {
+            return
+        }
+        *self.files.entry(filename.to_string()).or_default().entry(line).or_default() += hits;
+    }
+
+    /// Returns numbers of executed and instrumented lines.
+    pub fn summary(&self) -> (usize, usize) {
+        self.files.values().fold((0, 0), |(hit, found), lines| {
+            (hit + lines.values().filter(|hits| **hits != 0).count(), found + lines.len())
+        })
+    }
+
+    /// Merges LCOV tracefile into the counters, only `SF` and `DA` records are used.
+    pub fn merge_lcov(&mut self, text: &str) -> Status {
+        let mut filename = None;
+        for record in text.lines().map(str::trim) {
+            if let Some(name) = record.strip_prefix("SF:") {
+                filename = Some(name.to_string());
+            } else if let Some(data) = record.strip_prefix("DA:") {
+                let filename = filename.as_deref()
+                    .ok_or_else(|| format_err!("DA record without SF record: {}", record))?;
+                let mut fields = data.split(',');
+                let line = fields.next().and_then(|line| line.parse().ok());
+                let hits = fields.next().and_then(|hits| hits.parse().ok());
+                match (line, hits) {
+                    (Some(line), Some(hits)) => self.add(filename, line, hits),
+                    _ => return Err(format_err!("invalid DA record: {}", record)),
+                }
+            } else if record == "end_of_record" {
+                filename = None;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lcov(&self) -> String {
+        let mut result = String::new();
+        for (filename, lines) in &self.files {
+            result += &format!("TN:\nSF:{}\n", filename);
+            for (line, hits) in lines {
+                result += &format!("DA:{},{}\n", line, hits);
+            }
+            let hit = lines.values().filter(|hits| **hits != 0).count();
+            result += &format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit);
+        }
+        result
+    }
+
+    /// Writes LCOV tracefile adding up the counters with the existing file, if any.
+    pub fn save_lcov(&mut self, filename: &str) -> Status {
+        if Path::new(filename).exists() {
+            let text = std::fs::read_to_string(filename)
+                .map_err(|e| format_err!("failed to read coverage file {}: {}", filename, e))?;
+            self.merge_lcov(&text)?;
+        }
+        std::fs::write(filename, self.lcov())
+            .map_err(|e| format_err!("failed to write coverage file {}: {}", filename, e))
+    }
+}
+
+impl TraceHook for Coverage {
+    fn on_step(&mut self, _engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        if let (EngineTraceInfoType::Normal | EngineTraceInfoType::Implicit, Some(pos)) = (&info.info_type, position) {
+            self.add(&pos.filename, pos.line, 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcov_merge() {
+        let mut coverage = Coverage::default();
+        coverage.add("Wallet.sol", 10, 0);
+        coverage.add("Wallet.sol", 12, 3);
+        coverage.merge_lcov("TN:\nSF:Wallet.sol\nDA:10,1\nDA:12,2\nLF:2\nLH:2\nend_of_record\nTN:\nSF:Lib.sol\nDA:5,0\nend_of_record\n").unwrap();
+        assert_eq!(coverage.summary(), (2, 3));
+        assert_eq!(
+            coverage.lcov(),
+            "TN:\nSF:Lib.sol\nDA:5,0\nLF:1\nLH:0\nend_of_record\nTN:\nSF:Wallet.sol\nDA:10,1\nDA:12,5\nLF:2\nLH:2\nend_of_record\n"
+        );
+        assert!(coverage.merge_lcov("DA:1,1\n").is_err());
+    }
+}
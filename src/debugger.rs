@@ -0,0 +1,268 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::io::{BufRead, Write};
+
+use failure::{bail, format_err};
+use ton_labs_assembler::DbgPos;
+use ton_types::Result;
+use ton_vm::{
+    executor::{Engine, EngineTraceInfo, EngineTraceInfoType},
+    stack::StackItem,
+};
+
+use crate::printer::tree_of_cells_printer;
+use crate::testcall::TraceHook;
+
+const HELP: &str = "\
+Commands:
+  b <file>:<line>  set breakpoint at source line
+  b <line>         set breakpoint at line of any source file
+  b <INSN>         set breakpoint at instruction, e.g. b ACCEPT
+  b #<step>        set breakpoint at step number
+  bl               list breakpoints
+  d <n>            delete breakpoint number n
+  s                step one instruction
+  n                run to the next source line
+  c                continue to the next breakpoint
+  p                print stack
+  r                print control registers
+  c4               print persistent data (c4)
+  q                detach debugger and run to the end
+  h                print this help";
+
+#[derive(Debug, PartialEq)]
+pub enum Breakpoint {
+    Line { filename: Option<String>, line: usize },
+    Instruction(String),
+    Step(u32),
+}
+
+impl Breakpoint {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if let Some(step) = spec.strip_prefix('#') {
+            let step = step.parse().map_err(|e| format_err!("invalid step number {}: {}", step, e))?;
+            return Ok(Breakpoint::Step(step))
+        }
+        if let Some((filename, line)) = spec.rsplit_once(':') {
+            let line = line.parse().map_err(|e| format_err!("invalid line number {}: {}", line, e))?;
+            return Ok(Breakpoint::Line { filename: Some(filename.to_string()), line })
+        }
+        if let Ok(line) = spec.parse() {
+            return Ok(Breakpoint::Line { filename: None, line })
+        }
+        if spec.is_empty() {
+            bail!("empty breakpoint")
+        }
+        Ok(Breakpoint::Instruction(spec.to_uppercase()))
+    }
+
+    /// Line breakpoints are hit only when the execution enters the line.
+    fn is_hit(&self, step: u32, cmd_str: &str, position: Option<&DbgPos>, entered: bool) -> bool {
+        match self {
+            Breakpoint::Step(bp_step) => step == *bp_step,
+            Breakpoint::Instruction(name) => cmd_str.split_whitespace().next() == Some(name.as_str()),
+            Breakpoint::Line { filename, line } => match position {
+                Some(pos) => entered && pos.line == *line
+                    && filename.as_ref().is_none_or(|f| pos.filename.ends_with(f.as_str())),
+                None => false,
+            }
+        }
+    }
+}
+
+enum Mode {
+    Step,
+    NextLine(Option<(String, usize)>),
+    Continue,
+    Detached,
+}
+
+/// Interactive debugger driven by commands from stdin.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    mode: Mode,
+    /// Source line of the last mapped instruction
+    line: Option<(String, usize)>,
+}
+
+impl Debugger {
+    /// Starts in step mode if there are no breakpoints.
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        let mode = if breakpoints.is_empty() { Mode::Step } else { Mode::Continue };
+        Self { breakpoints, mode, line: None }
+    }
+
+    fn should_stop(&mut self, step: u32, cmd_str: &str, position: Option<&DbgPos>) -> bool {
+        let mut entered = false;
+        if let Some(pos) = position {
+            let line = Some((pos.filename.clone(), pos.line));
+            entered = self.line != line;
+            self.line = line;
+        }
+        let stop = match &self.mode {
+            Mode::Step => true,
+            Mode::NextLine(start) => match position {
+                Some(pos) => start.as_ref() != Some(&(pos.filename.clone(), pos.line)),
+                None => false,
+            },
+            Mode::Continue => false,
+            Mode::Detached => return false,
+        };
+        stop || self.breakpoints.iter().any(|bp| bp.is_hit(step, cmd_str, position, entered))
+    }
+
+    fn print_location(info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        print!("step {}: {} (gas {})", info.step, info.cmd_str, info.gas_used);
+        if let Some(pos) = position {
+            print!(" at {}:{}", pos.filename, pos.line);
+        }
+        println!();
+    }
+
+    fn print_c4(engine: &Engine) {
+        match engine.ctrl(4) {
+            Ok(StackItem::Cell(cell)) => print!("{}", tree_of_cells_printer(cell)),
+            Ok(item) => println!("c4 is not a cell: {}", item),
+            Err(err) => println!("c4 is not available: {}", err),
+        }
+    }
+
+    fn read_command() -> Option<String> {
+        print!("(tvm-dbg) ");
+        std::io::stdout().flush().ok();
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim().to_string()),
+        }
+    }
+
+    /// Processes commands until execution is resumed.
+    fn prompt(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        loop {
+            let command = match Self::read_command() {
+                Some(command) => command,
+                None => {
+                    self.mode = Mode::Detached;
+                    return
+                }
+            };
+            let (command, arg) = command.split_once(' ').unwrap_or((command.as_str(), ""));
+            match command {
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return
+                }
+                "n" | "next" => {
+                    self.mode = Mode::NextLine(position.map(|pos| (pos.filename.clone(), pos.line)));
+                    return
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Continue;
+                    return
+                }
+                "q" | "quit" => {
+                    self.mode = Mode::Detached;
+                    return
+                }
+                "b" | "break" => match Breakpoint::parse(arg) {
+                    Ok(bp) => {
+                        println!("Breakpoint {}: {:?}", self.breakpoints.len() + 1, bp);
+                        self.breakpoints.push(bp);
+                    }
+                    Err(err) => println!("{}", err),
+                }
+                "bl" => {
+                    for (i, bp) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {:?}", i + 1, bp);
+                    }
+                }
+                "d" | "delete" => match arg.parse::<usize>() {
+                    Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                        self.breakpoints.remove(n - 1);
+                    }
+                    _ => println!("invalid breakpoint number {}", arg),
+                }
+                "p" | "stack" => {
+                    for item in info.stack.iter() {
+                        println!("{}", item);
+                    }
+                }
+                "r" | "regs" => println!("{}", engine.dump_ctrls(false)),
+                "c4" | "data" => Self::print_c4(engine),
+                "where" => Self::print_location(info, position),
+                "h" | "help" => println!("{}", HELP),
+                "" => {}
+                _ => println!("unknown command {}, type h for help", command),
+            }
+        }
+    }
+}
+
+impl TraceHook for Debugger {
+    fn on_step(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        match info.info_type {
+            EngineTraceInfoType::Normal | EngineTraceInfoType::Implicit => (),
+            EngineTraceInfoType::Exception => {
+                println!("Exception: {}", info.cmd_str);
+                if matches!(self.mode, Mode::Detached) {
+                    return
+                }
+                Self::print_location(info, position);
+                self.prompt(engine, info, position);
+                return
+            }
+            _ => return,
+        }
+        if self.should_stop(info.step, &info.cmd_str, position) {
+            Self::print_location(info, position);
+            self.prompt(engine, info, position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(Breakpoint::parse("#15").unwrap(), Breakpoint::Step(15));
+        assert_eq!(Breakpoint::parse("accept").unwrap(), Breakpoint::Instruction("ACCEPT".to_string()));
+        assert_eq!(Breakpoint::parse("42").unwrap(), Breakpoint::Line { filename: None, line: 42 });
+        assert_eq!(
+            Breakpoint::parse("Wallet.sol:42").unwrap(),
+            Breakpoint::Line { filename: Some("Wallet.sol".to_string()), line: 42 }
+        );
+        assert!(Breakpoint::parse("Wallet.sol:x").is_err());
+        assert!(Breakpoint::parse("#x").is_err());
+    }
+
+    #[test]
+    fn test_line_breakpoint_on_entering() {
+        let pos = |line: usize| DbgPos { filename: "a.sol".to_string(), line };
+        let mut debugger = Debugger::new(vec![Breakpoint::parse("a.sol:5").unwrap()]);
+        let trace = [(4, "PUSHINT 1"), (5, "PUSHINT 2"), (5, "ADD"), (5, "PUSHINT 3"), (6, "DROP"), (5, "INC")];
+        let stops: Vec<bool> = trace.iter().enumerate()
+            .map(|(step, (line, cmd))| debugger.should_stop(step as u32 + 1, cmd, Some(&pos(*line))))
+            .collect();
+        // continue from the breakpoint leaves the line, the loop back to it stops again
+        assert_eq!(stops, vec![false, true, false, false, false, true]);
+        assert!(!debugger.should_stop(7, "RET", None));
+
+        debugger.mode = Mode::NextLine(Some(("a.sol".to_string(), 5)));
+        assert!(!debugger.should_stop(8, "DEC", Some(&pos(5))));
+        assert!(debugger.should_stop(9, "DROP", Some(&pos(6))));
+    }
+}
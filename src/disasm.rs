@@ -0,0 +1,204 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::collections::HashSet;
+
+use failure::format_err;
+use ton_labs_assembler::disasm_ex;
+use ton_types::{Cell, HashmapE, HashmapType, Result, SliceData, Status};
+
+use crate::printer::tree_of_cells_printer;
+use crate::program::load_from_file;
+
+const SETCP0: u64 = 0xFF00;
+const DICTPUSHCONST_PREFIX: u64 = 0x1E94; // F4A4_ without completion tag
+const FUNC_METHOD_ID_BITS: usize = 19;
+/// DICTIGETJMP, DICTUGETJMP, DICTIGETEXEC, DICTUGETEXEC and their Z versions,
+/// the lowest bit is set for the unsigned keys.
+const DICT_GET_JUMPS: [u64; 8] = [0xF4A0, 0xF4A1, 0xF4A2, 0xF4A3, 0xF4BC, 0xF4BD, 0xF4BE, 0xF4BF];
+
+/// Dispatcher dictionary in the root cell of the contract code.
+struct Dispatcher {
+    dict: HashmapE,
+    /// Keys are signed if the dictionary is used by DICTIGET* instruction
+    signed: bool,
+}
+
+impl Dispatcher {
+    fn compiler(&self) -> &'static str {
+        match (self.dict.bit_len(), self.signed) {
+            (FUNC_METHOD_ID_BITS, true) => "FunC contract",
+            (32, false) => "Solidity contract",
+            (32, true) => "Linked contract",
+            _ => "Contract with dispatcher",
+        }
+    }
+}
+
+/// Recognizes `SETCP0; <dict> n DICTPUSHCONST; DICT(I|U)GET(JMP|EXEC)[Z]` at the beginning of the code cell.
+fn recognize_layout(code: &Cell) -> Option<Dispatcher> {
+    let mut slice = SliceData::load_cell_ref(code).ok()?;
+    let mut read_dispatcher = || -> Result<Option<Dispatcher>> {
+        if slice.get_next_int(16)? != SETCP0 || slice.get_next_int(13)? != DICTPUSHCONST_PREFIX || !slice.get_next_bit()? {
+            return Ok(None)
+        }
+        let dict = slice.checked_drain_reference()?;
+        let bits = slice.get_next_int(10)? as usize;
+        let jump = slice.get_next_int(16)?;
+        if !DICT_GET_JUMPS.contains(&jump) {
+            return Ok(None)
+        }
+        Ok(Some(Dispatcher { dict: HashmapE::with_hashmap(bits, Some(dict)), signed: jump & 1 == 0 }))
+    };
+    read_dispatcher().ok().flatten()
+}
+
+fn disasm_cell(cell: &Cell, collapsed: bool) -> Result<String> {
+    let mut slice = SliceData::load_cell_ref(cell)?;
+    disasm_ex(&mut slice, collapsed)
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("  {}\n", line)).collect()
+}
+
+fn disasm_dict(dict: &HashmapE, signed: bool) -> Result<String> {
+    let bits = dict.bit_len();
+    let mut result = String::new();
+    dict.iterate_slices(|mut key, mut value| {
+        let raw = key.get_next_int(bits)?;
+        let id = if signed && raw >> (bits - 1) != 0 {
+            raw as i64 - (1i64 << bits)
+        } else {
+            raw as i64
+        };
+        result += &format!("method {} (0x{:x}):\n", id, raw);
+        result += &indent(&disasm_ex(&mut value, false)?);
+        Ok(true)
+    })?;
+    Ok(result)
+}
+
+pub fn disasm_text(code: &Cell, raw: bool) -> Result<String> {
+    let dispatcher = if raw { None } else { recognize_layout(code) };
+    Ok(match dispatcher {
+        Some(dispatcher) => format!(
+            "{}\nselector:\n{}{}",
+            dispatcher.compiler(),
+            indent(&disasm_cell(code, true)?),
+            disasm_dict(&dispatcher.dict, dispatcher.signed)?,
+        ),
+        None => disasm_cell(code, false)?,
+    })
+}
+
+/// Produces a DOT representation of the DAG of cells.
+pub fn graphviz(root: &Cell) -> String {
+    let mut result = "digraph cells {\n  node [shape=box];\n".to_string();
+    let mut visited = HashSet::new();
+    let mut queue = vec![root.clone()];
+    while let Some(cell) = queue.pop() {
+        let hash = cell.repr_hash();
+        if !visited.insert(hash.clone()) {
+            continue
+        }
+        let name = &hash.to_hex_string()[..16];
+        result += &format!("  \"{}\" [label=\"{}\\n{}b {}r\"];\n", name, name, cell.bit_length(), cell.references_count());
+        for i in 0..cell.references_count() {
+            if let Ok(child) = cell.reference(i) {
+                result += &format!("  \"{}\" -> \"{}\";\n", name, &child.repr_hash().to_hex_string()[..16]);
+                queue.push(child);
+            }
+        }
+    }
+    result += "}\n";
+    result
+}
+
+fn load_code(filename: &str) -> Result<Cell> {
+    load_from_file(filename)?.code
+        .ok_or_else(|| format_err!("{} has no code", filename))
+}
+
+pub fn disasm_dump_command(filename: &str) -> Status {
+    let state = load_from_file(filename)?;
+    if let Some(code) = &state.code {
+        println!("code:\n{}", tree_of_cells_printer(code));
+    }
+    if let Some(data) = &state.data {
+        println!("data:\n{}", tree_of_cells_printer(data));
+    }
+    Ok(())
+}
+
+pub fn disasm_text_command(filename: &str, raw: bool) -> Status {
+    print!("{}", disasm_text(&load_code(filename)?, raw)?);
+    Ok(())
+}
+
+pub fn disasm_graphviz_command(filename: &str) -> Status {
+    print!("{}", graphviz(&load_code(filename)?));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_types::{BuilderData, IBitstring};
+
+    #[test]
+    fn test_graphviz_unique_cells() {
+        let leaf = BuilderData::with_raw(vec![0x55], 8).unwrap().into_cell().unwrap();
+        let mut root = BuilderData::with_raw(vec![0xAA], 8).unwrap();
+        root.checked_append_reference(leaf.clone()).unwrap();
+        root.checked_append_reference(leaf).unwrap();
+        let dot = graphviz(&root.into_cell().unwrap());
+        assert_eq!(dot.matches("[label=").count(), 2);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+    }
+
+    /// `SETCP0; <dict> bits DICTPUSHCONST; jump` with the single function -1.
+    fn dispatcher_code(bits: usize, jump: u64) -> Cell {
+        let mut key = BuilderData::new();
+        key.append_bits(usize::MAX, bits).unwrap();
+        let mut dict = HashmapE::with_bit_len(bits);
+        let value = BuilderData::with_raw(vec![0x71], 8).unwrap(); // PUSHINT 1
+        dict.set_builder(SliceData::load_builder(key).unwrap(), &value).unwrap();
+        let mut code = BuilderData::new();
+        code.append_bits(SETCP0 as usize, 16).unwrap();
+        code.append_bits(DICTPUSHCONST_PREFIX as usize, 13).unwrap();
+        code.append_bit_one().unwrap();
+        code.checked_append_reference(dict.data().unwrap().clone()).unwrap();
+        code.append_bits(bits, 10).unwrap();
+        code.append_bits(jump as usize, 16).unwrap();
+        code.into_cell().unwrap()
+    }
+
+    #[test]
+    fn test_recognize_layout() {
+        let code = BuilderData::with_raw(vec![0xFF, 0x00], 16).unwrap().into_cell().unwrap();
+        assert!(recognize_layout(&code).is_none());
+        assert!(recognize_layout(&dispatcher_code(32, 0x71)).is_none());
+
+        let func = recognize_layout(&dispatcher_code(FUNC_METHOD_ID_BITS, 0xF4BC)).unwrap();
+        assert_eq!((func.compiler(), func.signed), ("FunC contract", true));
+        assert!(disasm_dict(&func.dict, func.signed).unwrap().starts_with("method -1 (0x7ffff):"));
+
+        let solidity = recognize_layout(&dispatcher_code(32, 0xF4BD)).unwrap();
+        assert_eq!((solidity.compiler(), solidity.signed), ("Solidity contract", false));
+        assert!(disasm_dict(&solidity.dict, solidity.signed).unwrap().starts_with("method 4294967295 (0xffffffff):"));
+
+        let linked = recognize_layout(&dispatcher_code(32, 0xF4BC)).unwrap();
+        assert_eq!((linked.compiler(), linked.signed), ("Linked contract", true));
+        assert!(disasm_dict(&linked.dict, linked.signed).unwrap().starts_with("method -1 (0xffffffff):"));
+    }
+}
@@ -0,0 +1,249 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::collections::HashMap;
+
+use failure::format_err;
+use serde::Serialize;
+use ton_labs_assembler::{disasm_ex, DbgPos};
+use ton_types::{Result, SliceData};
+use ton_vm::executor::{Engine, EngineTraceInfo, EngineTraceInfoType};
+
+use crate::callstack::CallStack;
+use crate::testcall::TraceHook;
+
+/// Standard TVM exit codes.
+const TVM_EXIT_CODES: [(i32, &str); 15] = [
+    (0, "Normal termination"),
+    (1, "Alternative normal termination"),
+    (2, "Stack underflow"),
+    (3, "Stack overflow"),
+    (4, "Integer overflow"),
+    (5, "Range check error: integer is out of the expected range"),
+    (6, "Invalid opcode"),
+    (7, "Type check error: argument of an instruction has wrong type"),
+    (8, "Cell overflow: too many bits or references are stored in a builder"),
+    (9, "Cell underflow: too many bits or references are read from a slice"),
+    (10, "Dictionary error"),
+    (11, "Unknown error, may be thrown by user programs"),
+    (12, "Fatal error"),
+    (13, "Out of gas"),
+    (-14, "Out of gas"),
+];
+
+/// Runtime error codes of the Solidity compiler.
+const SOLIDITY_EXIT_CODES: [(i32, &str); 28] = [
+    (40, "External inbound message has an invalid signature"),
+    (50, "Array index or index of <mapping>.at() is out of range"),
+    (51, "Contract's constructor has already been called"),
+    (52, "Replay protection exception"),
+    (53, "<address>.unpack() is called for a wrong address"),
+    (54, "<array>.pop() is called for an empty array"),
+    (55, "tvm.insertPubkey() is called for a wrong StateInit"),
+    (57, "External inbound message is expired"),
+    (58, "External inbound message has no signature but has public key"),
+    (60, "Inbound message has wrong function id"),
+    (61, "Deploying StateInit has no public key in data field"),
+    (62, "Reserved for internal usage"),
+    (63, "<optional(Type)>.get() is called for an empty optional"),
+    (64, "tvm.buildExtMsg() is called with wrong parameters"),
+    (65, "Call of an unassigned variable of function type"),
+    (66, "Integer is converted to a string with width less than the number length"),
+    (67, "gasToValue() or valueToGas() is called with wrong workchain id"),
+    (68, "There is no config parameter 20 or 21"),
+    (69, "Zero to the power of zero calculation"),
+    (70, "<string>.substr() is called with the substring longer than the whole string"),
+    (71, "Function marked by externalMsg is called by internal message"),
+    (72, "Function marked by internalMsg is called by external message"),
+    (73, "The value cannot be converted to enum type"),
+    (74, "Await answer message has wrong source address"),
+    (75, "Await answer message has wrong function id"),
+    (76, "Public function is called before constructor"),
+    (77, "Variant type cannot be converted to the target type"),
+    (78, "There is no private function with the function id"),
+];
+
+/// Descriptions of exit codes: standard TVM and Solidity ones and user-supplied codes
+/// overriding them.
+pub struct ExitCodes {
+    codes: HashMap<i32, String>,
+}
+
+impl Default for ExitCodes {
+    fn default() -> Self {
+        let codes = TVM_EXIT_CODES.iter().chain(SOLIDITY_EXIT_CODES.iter())
+            .map(|(code, description)| (*code, description.to_string()))
+            .collect();
+        Self { codes }
+    }
+}
+
+impl ExitCodes {
+    /// Loads user error codes from JSON object `{"<code>": "<description>", ...}`,
+    /// e.g. codes passed to Solidity `require`.
+    pub fn with_file(filename: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(filename)
+            .map_err(|e| format_err!("unable to read error codes file {}: {}", filename, e))?;
+        let user: HashMap<String, String> = serde_json::from_str(&text)
+            .map_err(|e| format_err!("error codes must be a JSON object of strings: {}", e))?;
+        let mut codes = Self::default();
+        for (code, description) in user {
+            let code = code.parse::<i32>().map_err(|e| format_err!("invalid error code {}: {}", code, e))?;
+            codes.codes.insert(code, description);
+        }
+        Ok(codes)
+    }
+
+    pub fn describe(&self, exit_code: i32) -> Option<&str> {
+        self.codes.get(&exit_code).map(String::as_str)
+    }
+}
+
+/// Explanation of the exit code of a failed execution.
+#[derive(Clone, Default, Serialize)]
+pub struct ErrorInfo {
+    pub description: Option<String>,
+    /// Source position of the instruction that threw the exception
+    pub position: Option<String>,
+    pub instruction: Option<String>,
+    /// Function frames from the innermost one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub backtrace: Vec<String>,
+}
+
+pub fn error_printer(exit_code: i32, info: &ErrorInfo) -> String {
+    let mut result = format!("Exit code {}: {}\n", exit_code, info.description.as_deref().unwrap_or("unknown error code"));
+    match (&info.position, &info.instruction) {
+        (Some(position), Some(insn)) => result += &format!("Thrown at {} by {}\n", position, insn),
+        (Some(position), None) => result += &format!("Thrown at {}\n", position),
+        (None, Some(insn)) => result += &format!("Thrown by {}\n", insn),
+        (None, None) => (),
+    }
+    if !info.backtrace.is_empty() {
+        result += "Backtrace:\n";
+        for frame in &info.backtrace {
+            result += &format!("  {}\n", frame);
+        }
+    }
+    result
+}
+
+/// Instruction with its source position.
+type Instruction = (Option<String>, Option<String>);
+
+/// Disassembles the instruction the code slice starts with.
+fn instruction_at(code: &SliceData) -> Option<String> {
+    let text = disasm_ex(&mut code.clone(), true).ok()?;
+    text.lines().map(str::trim).find(|line| !line.is_empty()).map(str::to_string)
+}
+
+/// Remembers the instruction raising an exception and the call stack at that moment.
+#[derive(Default)]
+pub struct ExceptionLocator {
+    call_stack: CallStack,
+    /// The throwing instruction and the backtrace at the moment of the exception
+    thrown: Option<(Instruction, Vec<String>)>,
+}
+
+impl ExceptionLocator {
+    /// Returns the error info if the exit code is not a successful one.
+    pub fn error_info(&self, exit_code: i32, codes: &ExitCodes) -> Option<ErrorInfo> {
+        if exit_code == 0 || exit_code == 1 {
+            return None
+        }
+        // out of gas may be not traced as an exception, the location of the last instruction is used
+        let ((instruction, position), backtrace) = match &self.thrown {
+            Some(thrown) => thrown.clone(),
+            None => ((None, self.call_stack.position()), self.call_stack.backtrace()),
+        };
+        Some(ErrorInfo {
+            description: codes.describe(exit_code).map(str::to_string),
+            position,
+            instruction,
+            backtrace,
+        })
+    }
+}
+
+impl TraceHook for ExceptionLocator {
+    fn on_step(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        match info.info_type {
+            EngineTraceInfoType::Normal | EngineTraceInfoType::Implicit => {
+                self.call_stack.update(engine, info, position);
+            }
+            // the throwing instruction has no normal trace, the exception event points to it;
+            // the last exception is the one which is not caught
+            EngineTraceInfoType::Exception => {
+                self.call_stack.update(engine, info, position);
+                let position = position.map(|pos| format!("{}:{}", pos.filename, pos.line));
+                let thrown = (instruction_at(&info.cmd_code), position);
+                self.thrown = Some((thrown, self.call_stack.backtrace()));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use ton_labs_assembler::{compile_code_debuggable, Line};
+    use ton_vm::executor::gas::gas_state::Gas;
+    use crate::testcall::{create_trace_callback, TraceLevel};
+
+    /// Runs the code and feeds its trace to the locator.
+    fn locate(source: &[&str]) -> Arc<Mutex<ExceptionLocator>> {
+        let lines = source.iter().enumerate()
+            .map(|(i, line)| Line::new(&format!("{}\n", line), "test.code", i + 1))
+            .collect();
+        let (code, dbg_info) = compile_code_debuggable(lines).unwrap();
+        let locator = Arc::new(Mutex::new(ExceptionLocator::default()));
+        let callback = create_trace_callback(TraceLevel::None, Some(dbg_info), vec![locator.clone()]).unwrap();
+        let mut engine = Engine::with_capabilities(0)
+            .setup_with_libraries(code, None, None, Some(Gas::test()), vec![]);
+        engine.set_trace(0);
+        engine.set_trace_callback(move |engine, info| callback(engine, info));
+        assert!(engine.execute().is_err());
+        locator
+    }
+
+    #[test]
+    fn test_error_info() {
+        let locator = locate(&["PUSHINT 1", "PUSHINT 0", "THROWIFNOT 101", "PUSHINT 2"]);
+        let locator = locator.lock().unwrap();
+        assert!(locator.error_info(0, &ExitCodes::default()).is_none());
+        let info = locator.error_info(101, &ExitCodes::default()).unwrap();
+        assert!(info.description.is_none());
+        assert_eq!(info.position.as_deref(), Some("test.code:3"));
+        assert_eq!(info.instruction.as_deref(), Some("THROWIFNOT 101"));
+
+        let info = locate(&["PUSHINT 0", "THROWIFNOT 51"]).lock().unwrap().error_info(51, &ExitCodes::default()).unwrap();
+        assert_eq!(
+            error_printer(51, &info),
+            "Exit code 51: Contract's constructor has already been called\nThrown at test.code:2 by THROWIFNOT 51\n\
+             Backtrace:\n  #0 test.code:2 in main\n"
+        );
+    }
+
+    #[test]
+    fn test_error_printer() {
+        let info = ErrorInfo {
+            backtrace: vec!["#0 a.sol:12 in a.sol:10".to_string(), "#1 a.sol:5 in main".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            error_printer(-14, &info),
+            "Exit code -14: unknown error code\nBacktrace:\n  #0 a.sol:12 in a.sol:10\n  #1 a.sol:5 in main\n"
+        );
+    }
+}
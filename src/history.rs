@@ -0,0 +1,159 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::path::{Path, PathBuf};
+
+use failure::{bail, format_err};
+use ton_types::{read_single_root_boc, Result, Status};
+
+/// Versions of a contract file kept in the `<file>.history` directory,
+/// version `n` is stored as `<n>.boc`.
+pub struct History {
+    file: PathBuf,
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn new(filename: &str) -> Self {
+        Self {
+            file: PathBuf::from(filename),
+            dir: PathBuf::from(format!("{}.history", filename)),
+        }
+    }
+
+    fn version_path(&self, version: u32) -> PathBuf {
+        self.dir.join(format!("{}.boc", version))
+    }
+
+    /// Returns recorded versions in ascending order.
+    pub fn versions(&self) -> Result<Vec<u32>> {
+        if !self.dir.exists() {
+            return Ok(vec![])
+        }
+        let mut versions = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "boc") {
+                if let Some(version) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse().ok()) {
+                    versions.push(version);
+                }
+            }
+        }
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    /// Records the current content of the file as the next version.
+    pub fn record(&self) -> Result<u32> {
+        self.record_file(&self.file)
+    }
+
+    fn record_file(&self, source: &Path) -> Result<u32> {
+        let version = self.versions()?.last().map_or(0, |last| last + 1);
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format_err!("unable to create history directory {}: {}", self.dir.display(), e))?;
+        std::fs::copy(source, self.version_path(version))
+            .map_err(|e| format_err!("unable to save state version {}: {}", version, e))?;
+        Ok(version)
+    }
+
+    /// Records the initial state from the input file if the history is empty. The input file
+    /// differs from the history one when the resulting state is saved to another file.
+    pub fn start(&self, input: &str) -> Status {
+        let input = Path::new(input);
+        if self.versions()?.is_empty() && input.exists() {
+            self.record_file(input)?;
+        }
+        Ok(())
+    }
+
+    /// Restores the file from the version. The restored state is recorded as a new version,
+    /// so the later versions are kept.
+    pub fn rollback(&self, version: u32) -> Result<u32> {
+        let path = self.version_path(version);
+        if !path.exists() {
+            bail!("state version {} is not found in {}", version, self.dir.display())
+        }
+        std::fs::copy(&path, &self.file)
+            .map_err(|e| format_err!("unable to restore {}: {}", self.file.display(), e))?;
+        self.record()
+    }
+
+    /// Lists versions with the hashes of their root cells, the current one is marked with `*`.
+    pub fn report(&self) -> Result<String> {
+        let current = file_hash(&self.file).ok();
+        let mut result = String::new();
+        for version in self.versions()? {
+            let hash = file_hash(&self.version_path(version))?;
+            let mark = if Some(&hash) == current.as_ref() { "*" } else { " " };
+            result += &format!("{} {:>4}: {}\n", mark, version, hash);
+        }
+        if result.is_empty() {
+            result += &format!("No history is recorded for {}\n", self.file.display());
+        }
+        Ok(result)
+    }
+}
+
+fn file_hash(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path).map_err(|e| format_err!("unable to read {}: {}", path.display(), e))?;
+    Ok(read_single_root_boc(bytes)?.repr_hash().to_hex_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_block::{Serializable, StateInit};
+    use ton_types::BuilderData;
+
+    fn write_state(filename: &str, data: u8) {
+        let state = StateInit {
+            data: Some(BuilderData::with_raw(vec![data], 8).unwrap().into_cell().unwrap()),
+            ..Default::default()
+        };
+        std::fs::write(filename, state.write_to_bytes().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_history_rollback() {
+        let filename = std::env::temp_dir().join("tvm_linker_test_history.tvc").to_string_lossy().to_string();
+        let history = History::new(&filename);
+        let _ = std::fs::remove_dir_all(format!("{}.history", filename));
+        write_state(&filename, 1);
+        history.start(&filename).unwrap();
+        history.start(&filename).unwrap();
+        write_state(&filename, 2);
+        assert_eq!(history.record().unwrap(), 1);
+        assert_eq!(history.versions().unwrap(), vec![0, 1]);
+
+        assert_eq!(history.rollback(0).unwrap(), 2);
+        assert_eq!(file_hash(Path::new(&filename)).unwrap(), file_hash(&history.version_path(0)).unwrap());
+        assert!(history.report().unwrap().starts_with("*    0:"));
+        assert!(history.rollback(5).is_err());
+    }
+
+    #[test]
+    fn test_history_start_from_input() {
+        let dir = std::env::temp_dir();
+        let input = dir.join("tvm_linker_test_history_input.tvc").to_string_lossy().to_string();
+        let output = dir.join("tvm_linker_test_history_output.tvc").to_string_lossy().to_string();
+        let history = History::new(&output);
+        let _ = std::fs::remove_dir_all(format!("{}.history", output));
+        write_state(&input, 1);
+        history.start(&input).unwrap();
+        write_state(&output, 2);
+        assert_eq!(history.record().unwrap(), 1);
+        assert_eq!(file_hash(&history.version_path(0)).unwrap(), file_hash(Path::new(&input)).unwrap());
+        assert_eq!(history.rollback(0).unwrap(), 2);
+        assert_eq!(file_hash(Path::new(&output)).unwrap(), file_hash(Path::new(&input)).unwrap());
+    }
+}
@@ -12,7 +12,22 @@
  */
 
 pub mod abi;
+pub mod acceptance;
+pub mod callstack;
+pub mod capabilities;
+pub mod compile;
+pub mod config;
+pub mod coverage;
+pub mod debugger;
+pub mod disasm;
+pub mod exit_codes;
+pub mod history;
 pub mod keyman;
+pub mod libraries;
 pub mod printer;
+pub mod profiler;
 pub mod program;
+pub mod replay;
+pub mod sandbox;
+pub mod scenario;
 pub mod testcall;
@@ -0,0 +1,209 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use failure::format_err;
+use ton_labs_assembler::DbgPos;
+use ton_types::{read_single_root_boc, BuilderData, Cell, HashmapE, IBitstring, Result, SliceData, Status, UInt256};
+use ton_vm::executor::{Engine, EngineTraceInfo};
+
+use crate::testcall::TraceHook;
+
+/// Library cells supplied to the engine in addition to the account's own libraries.
+pub struct Libraries {
+    dict: HashmapE,
+    /// Library hash -> file the library was loaded from
+    sources: BTreeMap<UInt256, String>,
+}
+
+impl Default for Libraries {
+    fn default() -> Self {
+        Self { dict: HashmapE::with_bit_len(256), sources: BTreeMap::new() }
+    }
+}
+
+impl Libraries {
+    /// Loads library BOC files and all `*.boc` files of the directories.
+    pub fn load(files: &[&str], dirs: &[&str]) -> Result<Self> {
+        let mut libraries = Self::default();
+        for file in files {
+            libraries.load_file(file)?;
+        }
+        for dir in dirs {
+            let entries = std::fs::read_dir(dir)
+                .map_err(|e| format_err!("unable to read library directory {}: {}", dir, e))?;
+            let mut paths = vec![];
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "boc") {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+            for path in paths {
+                libraries.load_file(&path.to_string_lossy())?;
+            }
+        }
+        Ok(libraries)
+    }
+
+    fn load_file(&mut self, filename: &str) -> Status {
+        let bytes = std::fs::read(filename)
+            .map_err(|e| format_err!("unable to read library {}: {}", filename, e))?;
+        let root = read_single_root_boc(bytes)
+            .map_err(|e| format_err!("library {} is not a valid BOC: {}", filename, e))?;
+        if let Some(stem) = Path::new(filename).file_stem().and_then(|stem| stem.to_str()) {
+            if stem.len() == 64 && stem != root.repr_hash().to_hex_string() {
+                eprintln!("WARNING: library {} has hash {}", filename, root.repr_hash().to_hex_string());
+            }
+        }
+        self.add(root, filename.to_string())
+    }
+
+    /// Adds library as a public library descriptor keyed by the hash of its root cell.
+    pub fn add(&mut self, root: Cell, source: String) -> Status {
+        let hash = root.repr_hash();
+        let key = SliceData::from_raw(hash.as_slice().to_vec(), 256);
+        let mut lib = BuilderData::new();
+        lib.append_bit_one()?;
+        lib.checked_append_reference(root)?;
+        self.dict.set_builder(key, &lib)?;
+        self.sources.insert(hash, source);
+        Ok(())
+    }
+
+    pub fn dict(&self) -> HashmapE {
+        self.dict.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Creates the hook tracking which of the libraries are executed.
+    pub fn tracker(&self) -> Result<LibraryTracker> {
+        let mut cells = HashMap::new();
+        for hash in self.sources.keys() {
+            let key = SliceData::from_raw(hash.as_slice().to_vec(), 256);
+            if let Some(lib) = self.dict.get(key)? {
+                let mut queue = vec![lib.reference(0)?];
+                while let Some(cell) = queue.pop() {
+                    if cells.insert(cell.repr_hash(), hash.clone()).is_none() {
+                        for i in 0..cell.references_count() {
+                            queue.push(cell.reference(i)?);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(LibraryTracker { cells, sources: self.sources.clone(), used: HashSet::new() })
+    }
+}
+
+/// Records libraries whose code cells were executed.
+pub struct LibraryTracker {
+    /// Cell hash -> hash of the library containing the cell
+    cells: HashMap<UInt256, UInt256>,
+    sources: BTreeMap<UInt256, String>,
+    used: HashSet<UInt256>,
+}
+
+impl LibraryTracker {
+    pub fn report(&self) -> String {
+        let mut result = String::new();
+        for (hash, source) in &self.sources {
+            if self.used.contains(hash) {
+                result += &format!("Library {} loaded from {}\n", hash.to_hex_string(), source);
+            }
+        }
+        if result.is_empty() {
+            result += "No supplied libraries were executed\n";
+        }
+        result
+    }
+}
+
+impl TraceHook for LibraryTracker {
+    fn on_step(&mut self, _engine: &Engine, info: &EngineTraceInfo, _position: Option<&DbgPos>) {
+        if let Some(lib) = self.cells.get(&info.cmd_code.cell().repr_hash()) {
+            self.used.insert(lib.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use ton_types::CellType;
+    use ton_vm::executor::gas::gas_state::Gas;
+
+    use crate::testcall::{create_trace_callback, TraceLevel};
+
+    #[test]
+    fn test_library_dict() {
+        let leaf = BuilderData::with_raw(vec![0x71], 8).unwrap().into_cell().unwrap();
+        let mut root = BuilderData::with_raw(vec![0x72], 8).unwrap();
+        root.checked_append_reference(leaf.clone()).unwrap();
+        let root = root.into_cell().unwrap();
+        let mut libraries = Libraries::default();
+        libraries.add(root.clone(), "lib.boc".to_string()).unwrap();
+        assert!(!libraries.is_empty());
+
+        let key = SliceData::from_raw(root.repr_hash().as_slice().to_vec(), 256);
+        let lib = libraries.dict().get(key).unwrap().unwrap();
+        assert_eq!(lib.reference(0).unwrap(), root);
+
+        let tracker = libraries.tracker().unwrap();
+        assert_eq!(tracker.cells.get(&leaf.repr_hash()), Some(&root.repr_hash()));
+        assert_eq!(tracker.report(), "No supplied libraries were executed\n");
+    }
+
+    /// Runs the code jumping to the library cell, returns the exit code and the library report.
+    fn run_library_call(libraries: &Libraries, lib: &Cell) -> (Result<i32>, String) {
+        let mut lib_ref = BuilderData::new();
+        lib_ref.set_type(CellType::LibraryReference);
+        lib_ref.append_u8(2).unwrap();
+        lib_ref.append_raw(lib.repr_hash().as_slice(), 256).unwrap();
+        let mut code = BuilderData::with_raw(vec![0xDB, 0x3D], 16).unwrap(); // JMPREF
+        code.checked_append_reference(lib_ref.into_cell().unwrap()).unwrap();
+
+        let tracker = Arc::new(Mutex::new(libraries.tracker().unwrap()));
+        let callback = create_trace_callback(TraceLevel::None, None, vec![tracker.clone()]).unwrap();
+        let code = SliceData::load_builder(code).unwrap();
+        let mut engine = Engine::with_capabilities(0)
+            .setup_with_libraries(code, None, None, Some(Gas::test()), vec![libraries.dict()]);
+        engine.set_trace(0);
+        engine.set_trace_callback(move |engine, info| callback(engine, info));
+        let exit_code = engine.execute();
+        let report = tracker.lock().unwrap().report();
+        (exit_code, report)
+    }
+
+    #[test]
+    fn test_library_call() {
+        let lib = BuilderData::with_raw(vec![0x77], 8).unwrap().into_cell().unwrap(); // PUSHINT 7
+        let mut libraries = Libraries::default();
+        libraries.add(lib.clone(), "lib.boc".to_string()).unwrap();
+        let (exit_code, report) = run_library_call(&libraries, &lib);
+        assert_eq!(exit_code.unwrap(), 0);
+        assert_eq!(report, format!("Library {} loaded from lib.boc\n", lib.repr_hash().to_hex_string()));
+
+        let (exit_code, report) = run_library_call(&Libraries::default(), &lib);
+        assert!(exit_code.is_err());
+        assert_eq!(report, "No supplied libraries were executed\n");
+    }
+}
@@ -12,27 +12,47 @@
  */
 
 mod abi;
+mod acceptance;
+mod callstack;
+mod capabilities;
+mod compile;
+mod config;
+mod coverage;
+mod debugger;
+mod disasm;
+mod exit_codes;
+mod history;
 mod keyman;
+mod libraries;
 mod printer;
+mod profiler;
 mod program;
+mod replay;
+mod sandbox;
+mod scenario;
 mod testcall;
 
-use std::{env, io::Write, fs::File, str::FromStr};
+use std::{env, io::Write, fs::File, str::FromStr, sync::{Arc, Mutex}};
 use clap::{clap_app, ArgMatches};
 use failure::{format_err, bail};
 
 use ton_block::{
-    Deserializable, Message, StateInit, Serializable, MsgAddressInt,
-    ExternalInboundMessageHeader, InternalMessageHeader, MsgAddressIntOrNone, ConfigParams
+    Account, Deserializable, Message, StateInit, Serializable, MsgAddressInt,
+    ExternalInboundMessageHeader, InternalMessageHeader, MsgAddressIntOrNone
 };
-use ton_types::{SliceData, Result, Status, AccountId, UInt256, BocWriter};
+use ton_types::{SliceData, Result, Status, AccountId, BocWriter};
 
-use abi::{build_abi_body, decode_body, load_abi_json_string, load_abi_contract};
+use abi::{build_abi_body, decode_body, load_abi_json_string, load_abi_contract, sign_abi_body};
+use coverage::Coverage;
+use debugger::{Breakpoint, Debugger};
+use exit_codes::ExitCodes;
+use history::History;
 use keyman::Keypair;
-use program::{get_now, save_to_file, load_from_file};
-use testcall::{call_contract, MsgInfo, TestCallParams, TraceLevel};
-
-const DEFAULT_CAPABILITIES: u64 = 0x880116ae; // Default capabilities on the main network
+use libraries::Libraries;
+use profiler::Profiler;
+use program::{get_now, load_account_from_file, load_from_file, save_account_to_file, save_to_file};
+use sandbox::accounts_dir;
+use testcall::{call_contract, execute_transaction, GetMethod, MsgInfo, SharedTraceHook, TestCallParams, TraceLevel};
 
 fn main() -> std::result::Result<(), i32> {
     linker_main().map_err(|err_str| {
@@ -53,12 +73,46 @@ fn linker_main() -> Status {
         (version: build_info.as_str())
         (author: "TON Labs")
         (about: "Tool for executing TVM code")
+        (@subcommand compile =>
+            (@setting AllowNegativeNumbers)
+            (about: "compile contract")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg INPUT: +required +takes_value "TVM assembler source file")
+            (@arg LIB: --lib +takes_value ... number_of_values(1) "Standard library source file. If not specified lib is loaded from environment variable TVM_LINKER_LIB_PATH if it exists.")
+            (@arg ABI_JSON: -a --("abi-json") +takes_value "Supplies contract abi to calculate correct function ids. If not specified abi can be loaded from file obtained from <INPUT> argument if it exists.")
+            (@arg WORKCHAIN: -w --workchain +takes_value "Workchain id used to print contract address, -1 by default.")
+            (@arg DEBUG: --debug "Prints the linked assembly source before compilation")
+            (@arg PRINT_CODE: --print_code "Prints code cell instead of saving the TVC file")
+            (@arg SILENT: --silent "Mutes all extra notifications")
+            (@arg DEBUG_MAP: --("debug-map") +takes_value "Generates debug map file")
+        )
+        (@subcommand disasm =>
+            (about: "disassemble a tvc file")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@subcommand dump =>
+                (about: "dump a tree of cells of code and data")
+                (@arg TVC: +required +takes_value "Path to tvc file")
+            )
+            (@subcommand text =>
+                (about: "disassemble the code cell, recognizing Solidity and FunC dispatchers")
+                (@arg RAW: --raw "Disassembles the code cell as is without recognizing a dispatcher")
+                (@arg TVC: +required +takes_value "Path to tvc file")
+            )
+            (@subcommand graphviz =>
+                (about: "print the code cells DAG in dot format")
+                (@arg TVC: +required +takes_value "Path to tvc file")
+            )
+            (@setting SubcommandRequired)
+        )
         (@subcommand decode =>
-            (about: "take apart a message boc or a tvc file")
+            (about: "take apart a message boc, a tvc file or an account boc")
             (version: build_info.as_str())
             (author: "TON Labs")
             (@arg INPUT: +required +takes_value "BOC file")
             (@arg TVC: --tvc "BOC file is tvc file")
+            (@arg ACCOUNT: --account conflicts_with[TVC] "BOC file is account state")
         )
         (@subcommand test =>
             (@setting AllowLeadingHyphen)
@@ -68,25 +122,80 @@ fn linker_main() -> Status {
             (@arg SOURCE: -s --source +takes_value "Contract source file")
             (@arg BODY: --body +takes_value "Body for external inbound message (a bitstring like x09c_ or a hex string)")
             (@arg BODY_FROM_BOC: --("body-from-boc") +takes_value "Body from message boc file")
-            (@arg SIGN: --sign +takes_value "Signs body with private key from defined file")
+            (@arg SIGN: --sign +takes_value "Signs body with private key from defined file, the signature layout follows the ABI supplied with --abi-json (ABI 1.0 layout without ABI)")
             (@arg TRACE: --trace "Prints last command name, stack and registers after each executed TVM command")
             (@arg TRACE_MIN: --("trace-minimal") "Prints minimal trace")
+            (@arg DEBUGGER: --debugger conflicts_with[JSON] "Runs the contract under the interactive debugger reading commands from stdin")
+            (@arg BREAK: --break +takes_value +multiple number_of_values(1) requires[DEBUGGER] "Sets debugger breakpoint: <file>:<line>, <line>, instruction name or #<step>")
+            (@arg PROFILE: --profile conflicts_with[JSON] "Prints gas and instruction counts per source line and per function")
+            (@arg FOLDED: --("profile-output") +takes_value requires[PROFILE] "Saves the gas profile as folded stacks for flamegraph tools")
+            (@arg COVERAGE: --coverage +takes_value "Adds executed source lines to LCOV coverage file (requires debug map)")
+            (@arg CHECK_ACCEPT: --("check-accept") conflicts_with[INTERNAL] conflicts_with[TICKTOCK] conflicts_with[TRANSACTION] conflicts_with[GETTER] conflicts_with[GASLIMIT] "Checks that external message is accepted within the gas credit and reports where it happens")
+            (@arg C7: --c7 +takes_value conflicts_with[TRANSACTION] "Loads SmartContractInfo (c7) values or the whole tuple from JSON file")
+            (@arg RAND_SEED: --("rand-seed") +takes_value conflicts_with[TRANSACTION] "Supplies random seed (256-bit hex) for c7")
+            (@arg BLOCK_LT: --("block-lt") +takes_value conflicts_with[TRANSACTION] "Supplies block logical time for c7")
+            (@arg TRANS_LT: --("trans-lt") +takes_value conflicts_with[TRANSACTION] "Supplies transaction logical time for c7")
+            (@arg INIT_CODE_HASH: --("init-code-hash") +takes_value conflicts_with[TRANSACTION] "Supplies initial code hash (256-bit hex) for c7, the hash of the contract code by default")
+            (@arg STORAGE_FEES: --("storage-fees") +takes_value conflicts_with[TRANSACTION] "Supplies storage fees collected in the transaction for c7")
+            (@arg LIBRARY: --library +takes_value +multiple number_of_values(1) "Supplies library cell BOC file to resolve library cells during execution")
+            (@arg LIBRARY_DIR: --("library-dir") +takes_value +multiple number_of_values(1) "Supplies directory with library cell BOC files")
             (@arg DECODEC6: --("decode-c6") "Prints last command name, stack and registers after each executed TVM command")
             (@arg INTERNAL: --internal +takes_value "Emulates inbound internal message with value instead of external message")
-            (@arg BOUNCED: --bounced requires[INTERNAL] "Emulates bounced message, can be used only with --internal option.")
-            (@arg BALANCE: --balance +takes_value "Emulates supplied account balance")
+            (@arg BOUNCED: --bounced requires[INTERNAL] "Emulates bounced message, can be used only with --internal option. The body (--body, --body-from-boc or ABI call) is the body of the original outbound message, it is converted to the bounced one")
+            (@arg BALANCE: --balance +takes_value "Emulates supplied account balance, the balance of the account BOC is used by default")
             (@arg SRCADDR: --src +takes_value "Supplies message source address")
             (@arg NOW: --now +takes_value "Supplies transaction creation unixtime")
             (@arg TICKTOCK: --ticktock +takes_value conflicts_with[BODY] "Emulates ticktock transaction in masterchain, 0 for tick and -1 for tock")
             (@arg GASLIMIT: -l --("gas-limit") +takes_value "Defines gas limit for tvm execution")
-            (@arg CONFIG: --config +takes_value "Imports config parameters from a config contract TVC")
-            (@arg INPUT: +required +takes_value "TVM assembler source file or contract name if used with test subcommand")
-            (@arg ADDRESS: --address +takes_value "Contract address, which can be obtained from the contract with `address(this)`. If not specified address can be obtained from the INPUT argument or set to zero.")
+            (@arg TRANSACTION: --transaction conflicts_with[TICKTOCK] conflicts_with[GASLIMIT] "Emulates the whole ordinary transaction: storage, credit, compute, action and bounce phases")
+            (@arg DELIVER: --deliver requires[TRANSACTION] "Delivers outbound internal messages to the destination contracts loaded from <address>.tvc files until the message queue is empty")
+            (@arg DEPLOY: --deploy requires[TRANSACTION] "Emulates deployment: the account is uninitialized and the contract StateInit is attached to the message, the constructor is run in the same transaction")
+            (@arg MSG_TIME: --time +takes_value conflicts_with[INTERNAL] "Supplies `time` ABI header in milliseconds: absolute value, `now` or offset in seconds relative to --now (e.g. -60 or now+5)")
+            (@arg EXPIRE: --expire +takes_value conflicts_with[INTERNAL] "Supplies `expire` ABI header: absolute unixtime, `now` or offset in seconds relative to --now (e.g. +60)")
+            (@arg CHECK_REPLAY: --("check-replay") requires[ABI_JSON] requires[ABI_METHOD] conflicts_with[INTERNAL] conflicts_with[TICKTOCK] conflicts_with[GETTER] "Checks replay protection: reports if the contract accepts the same message twice or an expired message, contract file is not modified")
+            (@arg ERROR_CODES: --("error-codes") +takes_value "Supplies JSON file mapping contract error codes to their descriptions to explain failures")
+            (@arg DRY_RUN: --("dry-run") conflicts_with[OUTPUT_STATE] "Does not save the resulting contract state")
+            (@arg OUTPUT_STATE: --("output-state") +takes_value "Saves the resulting contract state to the file instead of the input file")
+            (@arg HISTORY: --history conflicts_with[DRY_RUN] "Records every saved contract state in <file>.history directory, see history subcommand")
+            (@arg LAST_PAID: --("last-paid") +takes_value requires[TRANSACTION] "Supplies unixtime of the last storage fee payment for a contract loaded from TVC (equals to --now by default)")
+            (@arg CONFIG: --config +takes_value "Imports config parameters from a config contract TVC or from a JSON file")
+            (@arg CAPABILITIES: --capabilities +takes_value "Changes capabilities of the config (or the default ones): comma separated +Flag or -Flag list or the whole value")
+            (@arg INPUT: +required +takes_value "Contract name, TVC file or account BOC (*.boc) file, the account is updated in place")
+            (@arg ADDRESS: --address +takes_value "Contract address, which can be obtained from the contract with `address(this)`. If not specified address is taken from the account BOC, the INPUT argument or set to zero.")
+            (@arg GETTER: --getter conflicts_with[INTERNAL] conflicts_with[TICKTOCK] conflicts_with[TRANSACTION] conflicts_with[SIGN] "Runs get-method locally and prints its output, contract file is not modified")
+            (@arg METHOD_ID: --("method-id") +takes_value requires[GETTER] conflicts_with[ABI_METHOD] "Supplies FunC get-method id or name for --getter, arguments can be passed with --abi-params as a JSON array of integers")
+            (@arg JSON: --json "Prints the result of the execution in JSON format instead of the text output")
             (@arg DEBUG_MAP: -d --("debug-map") +takes_value "Supplies debug info json file")
-            (@arg ABI_JSON: -a --("abi-json") +takes_value conflicts_with[BODY] "Supplies json file with contract ABI")
+            (@arg ABI_JSON: -a --("abi-json") +takes_value "Supplies json file with contract ABI, with --body and --sign the body is signed as the ABI requires")
             (@arg ABI_METHOD: -m --("abi-method") +takes_value conflicts_with[BODY] "Supplies the name of the calling contract method")
             (@arg ABI_PARAMS: -p --("abi-params") +takes_value conflicts_with[BODY] "Supplies ABI arguments for the contract method (can be passed via filename). Can be not specified for empty parameters.")
-            (@arg ABI_HEADER: -r --("abi-header") +takes_value conflicts_with[BODY] conflicts_with[INTERNAL] "Supplies ABI header")
+            (@arg ABI_HEADER: -r --("abi-header") +takes_value conflicts_with[INTERNAL] "Supplies ABI header")
+        )
+        (@subcommand scenario =>
+            (about: "run a sequence of contract calls described in a JSON or YAML file")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg INPUT: +required +takes_value "Scenario file")
+            (@arg DEBUG_MAP: -d --("debug-map") +takes_value +multiple number_of_values(1) "Supplies debug info json file of the contracts")
+            (@arg COVERAGE: --coverage +takes_value requires[DEBUG_MAP] "Adds executed source lines to LCOV coverage file")
+        )
+        (@subcommand config =>
+            (about: "derive a modified config from the base config")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg BASE: +required +takes_value "Base config: config contract TVC or JSON file")
+            (@arg PATCH: -p --patch +takes_value "Config params to set in JSON (can be passed via filename), null value removes the param")
+            (@arg CAPABILITIES: --capabilities +takes_value "Changes capabilities of the config: comma separated +Flag or -Flag list or the whole value")
+            (@arg ENABLE: --enable +takes_value +multiple number_of_values(1) "Enables capability flag (name or mask)")
+            (@arg DISABLE: --disable +takes_value +multiple number_of_values(1) "Disables capability flag (name or mask)")
+            (@arg OUTPUT: -o --output +takes_value "Saves the resulting config to TVC or JSON file")
+        )
+        (@subcommand history =>
+            (about: "list or roll back contract states recorded by test --history")
+            (version: build_info.as_str())
+            (author: "TON Labs")
+            (@arg INPUT: +required +takes_value "Contract TVC or account BOC file")
+            (@arg ROLLBACK: --rollback +takes_value "Restores the contract file to the state version")
         )
         (@subcommand message =>
             (@setting AllowNegativeNumbers)
@@ -109,16 +218,54 @@ fn linker_main() -> Status {
     ).get_matches();
 
 
+    //SUBCOMMAND COMPILE
+    if let Some(compile_matches) = matches.subcommand_matches("compile") {
+        return run_compile(compile_matches);
+    }
+
     //SUBCOMMAND TEST
     if let Some(test_matches) = matches.subcommand_matches("test") {
         return run_test_subcmd(test_matches);
     }
 
+    //SUBCOMMAND SCENARIO
+    if let Some(scenario_matches) = matches.subcommand_matches("scenario") {
+        return run_scenario_subcmd(scenario_matches);
+    }
+
+    //SUBCOMMAND CONFIG
+    if let Some(config_matches) = matches.subcommand_matches("config") {
+        return run_config_subcmd(config_matches);
+    }
+
+    //SUBCOMMAND HISTORY
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        let history = History::new(history_matches.value_of("INPUT").unwrap());
+        if let Some(version) = history_matches.value_of("ROLLBACK") {
+            let version = version.parse::<u32>()
+                .map_err(|e| format_err!("failed to parse \"rollback\" option: {}", e))?;
+            println!("State version {} restored as version {}", version, history.rollback(version)?);
+        }
+        print!("{}", history.report()?);
+        return Ok(())
+    }
+
+    //SUBCOMMAND DISASM
+    if let Some(disasm_matches) = matches.subcommand_matches("disasm") {
+        return match disasm_matches.subcommand() {
+            ("dump", Some(m)) => disasm::disasm_dump_command(m.value_of("TVC").unwrap()),
+            ("text", Some(m)) => disasm::disasm_text_command(m.value_of("TVC").unwrap(), m.is_present("RAW")),
+            ("graphviz", Some(m)) => disasm::disasm_graphviz_command(m.value_of("TVC").unwrap()),
+            _ => unreachable!(),
+        }
+    }
+
     //SUBCOMMAND DECODE
     if let Some(decode_matches) = matches.subcommand_matches("decode") {
         return decode_boc(
             decode_matches.value_of("INPUT").unwrap(),
             decode_matches.is_present("TVC"),
+            decode_matches.is_present("ACCOUNT"),
         );
     }
 
@@ -142,7 +289,11 @@ fn linker_main() -> Status {
                 Some(body)
             },
             None => {
-                build_body(msg_matches, msg_matches.value_of("ADDRESS").map(|s| s.to_string()))?
+                build_body(
+                    msg_matches,
+                    msg_matches.value_of("ADDRESS").map(|s| s.to_string()),
+                    msg_matches.value_of("ABI_HEADER").map(|s| s.to_string()),
+                )?
             },
         };
 
@@ -196,7 +347,46 @@ fn decode_hex_string(hex_str: String) -> Result<(Vec<u8>, usize)> {
     }
 }
 
-fn decode_boc(filename: &str, is_tvc: bool) -> Status {
+fn run_compile(matches: &ArgMatches) -> Status {
+    let input = matches.value_of("INPUT").unwrap();
+    let libs = matches.values_of("LIB")
+        .map(|values| values.map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+    let libs = compile::resolve_libs(libs);
+    let abi_file = compile::resolve_abi_file(input, matches.value_of("ABI_JSON"));
+    let wc = matches.value_of("WORKCHAIN")
+        .map(|wc| wc.parse::<i8>())
+        .transpose()
+        .map_err(|e| format_err!("failed to parse workchain id: {}", e))?
+        .unwrap_or(-1);
+    let silent = matches.is_present("SILENT");
+
+    let (code, dbg_info) = compile::compile(input, &libs, abi_file.as_deref(), matches.is_present("DEBUG"))?;
+    if let Some(debug_map) = matches.value_of("DEBUG_MAP") {
+        compile::save_debug_map(&dbg_info, debug_map)?;
+        if !silent {
+            println!("Debug map saved to file {}", debug_map);
+        }
+    }
+    if matches.is_present("PRINT_CODE") {
+        println!("code: {}", printer::tree_of_cells_into_base64(Some(&code)));
+        return Ok(())
+    }
+    save_to_file(compile::create_state_init(code)?, None, wc, silent)?;
+    Ok(())
+}
+
+fn decode_boc(filename: &str, is_tvc: bool, is_account: bool) -> Status {
+    if is_account {
+        let bytes = std::fs::read(filename)?;
+        let account = Account::construct_from_bytes(&bytes)?;
+        println!("Encoded: {}\n", hex::encode(bytes));
+        println!("Decoded:\n{}", printer::account_printer(&account));
+        if let Some(state) = account.state_init() {
+            println!("{}", printer::state_init_printer(state));
+        }
+        return Ok(())
+    }
     let (mut root_slice, orig_bytes) = program::load_stateinit(filename)?;
 
     println!("Encoded: {}\n", hex::encode(orig_bytes));
@@ -212,35 +402,66 @@ fn decode_boc(filename: &str, is_tvc: bool) -> Status {
 
 fn run_test_subcmd(matches: &ArgMatches) -> Status {
     let input = matches.value_of("INPUT").unwrap();
-    let addr_from_input = if hex::decode(input).is_ok() {
+    let contract_file = if input.ends_with(".tvc") || input.ends_with(".boc") {
+        input.to_owned()
+    } else {
+        format!("{}.tvc", input)
+    };
+    let account = load_account_from_file(&contract_file)?;
+    let deploy = matches.is_present("DEPLOY");
+    if deploy && account.is_some() {
+        bail!("deployment requires the contract TVC file, not an account BOC")
+    }
+    let addr_from_input = if let Some(addr) = account.as_ref().and_then(|account| account.get_addr()) {
+        addr.to_string()
+    } else if deploy {
+        load_from_file(&contract_file)?.hash()?.to_hex_string()
+    } else if hex::decode(input).is_ok() {
         input.to_owned()
     } else {
         "0".repeat(64)
     };
     let address = matches.value_of("ADDRESS").unwrap_or(&addr_from_input);
+    let getter = matches.is_present("GETTER");
+    let get_method = match matches.value_of("METHOD_ID") {
+        Some(method) => Some(GetMethod {
+            id: testcall::parse_method_id(method),
+            args: testcall::parse_get_method_args(matches.value_of("ABI_PARAMS").unwrap_or("[]"))?,
+        }),
+        None => None,
+    };
+    let now = parse_now(matches.value_of("NOW"))?;
+    let header = abi_header(matches, now)?;
     let (body, sign) = match matches.value_of("BODY") {
         Some(hex_str) => {
             let (buf, buf_bits) = decode_hex_string(hex_str.to_string())?;
             let body = SliceData::from_raw(buf, buf_bits);
-            (Some(body), Some(matches.value_of("SIGN")))
+            match (matches.value_of("ABI_JSON"), matches.value_of("SIGN")) {
+                (Some(abi_file), Some(key_file)) if !matches.is_present("INTERNAL") => {
+                    let body = sign_abi_body(
+                        abi_file,
+                        body,
+                        header.as_ref().map(ToString::to_string).as_deref(),
+                        Keypair::from_file(key_file)?,
+                        Some(address),
+                    )?;
+                    (Some(body), None)
+                }
+                _ => (Some(body), Some(matches.value_of("SIGN"))),
+            }
         },
-        None => (build_body(matches, Some(address.to_string()))?, None),
+        None if get_method.is_some() => (None, None),
+        None => (build_body(matches, Some(address.to_string()), header.as_ref().map(ToString::to_string))?, None),
     };
 
     let ticktock = parse_ticktock(matches.value_of("TICKTOCK"))?;
-    let now = parse_now(matches.value_of("NOW"))?;
 
     let action_decoder = |body, is_internal| {
-        let abi_file = matches.value_of("ABI_JSON");
-        let method = matches.value_of("ABI_METHOD");
-        if let Some(abi_file) = abi_file {
-            if let Some(method) = method {
-                let result = decode_body(abi_file, method, body, is_internal)
-                    .unwrap_or_default();
-                println!("{}", result);
-            }
-        }
+        let abi_file = matches.value_of("ABI_JSON")?;
+        let method = matches.value_of("ABI_METHOD")?;
+        decode_body(abi_file, method, body, is_internal).ok()
     };
+    let json = matches.is_present("JSON");
 
     let abi_json = matches.value_of("ABI_JSON");
 
@@ -265,23 +486,31 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
             }
             res
         });
-    if let Some(map) = debug_map_filename.clone() {
-        println!("DEBUG_MAP: {map}");
+    if !json {
+        if let Some(map) = debug_map_filename.clone() {
+            println!("DEBUG_MAP: {map}");
+        }
+        println!("TEST STARTED");
+        println!("body = {:?}", body);
     }
-    println!("TEST STARTED");
-    println!("body = {:?}", body);
 
+    let original_msg = matches.value_of("BODY_FROM_BOC").map(|filename| -> Result<Message> {
+        let (mut root_slice, _) = program::load_stateinit(filename)?;
+        Message::construct_from(&mut root_slice)
+    }).transpose()?;
+    // bounced message comes back from the destination of the original one
+    let bounce_src = match &original_msg {
+        Some(msg) if matches.is_present("BOUNCED") => msg.dst().map(|dst| dst.to_string()),
+        _ => None,
+    };
     let mut msg_info = MsgInfo {
         balance: matches.value_of("INTERNAL"),
-        src: matches.value_of("SRCADDR"),
+        src: matches.value_of("SRCADDR").or(bounce_src.as_deref()),
         now,
         bounced: matches.is_present("BOUNCED"),
         body,
     };
-
-    if let Some(filename) = matches.value_of("BODY_FROM_BOC") {
-        let (mut root_slice, _) = program::load_stateinit(filename)?;
-        let msg = Message::construct_from(&mut root_slice)?;
+    if let Some(msg) = original_msg {
         msg_info.body = msg.body();
     }
 
@@ -296,55 +525,302 @@ fn run_test_subcmd(matches: &ArgMatches) -> Status {
         trace_level = TraceLevel::Minimal;
     }
 
-
-    let input = if input.ends_with(".tvc") {
-        input.to_owned()
+    let mut trace_hooks: Vec<SharedTraceHook> = vec![];
+    if matches.is_present("DEBUGGER") {
+        let breakpoints = matches.values_of("BREAK")
+            .map(|values| values.map(Breakpoint::parse).collect::<Result<Vec<_>>>())
+            .transpose()?
+            .unwrap_or_default();
+        trace_hooks.push(Arc::new(Mutex::new(Debugger::new(breakpoints))));
+    }
+    let debug_info = testcall::load_debug_info(&debug_map_filename.unwrap_or("".to_string()));
+    let coverage = match matches.value_of("COVERAGE") {
+        Some(_) => {
+            let coverage = Arc::new(Mutex::new(Coverage::with_debug_info(debug_info.as_ref())?));
+            trace_hooks.push(coverage.clone());
+            Some(coverage)
+        }
+        None => None
+    };
+    let libraries = Libraries::load(
+        &matches.values_of("LIBRARY").map(Iterator::collect::<Vec<_>>).unwrap_or_default(),
+        &matches.values_of("LIBRARY_DIR").map(Iterator::collect::<Vec<_>>).unwrap_or_default(),
+    )?;
+    let library_tracker = if libraries.is_empty() {
+        None
     } else {
-        format!("{}.tvc", input)
+        let tracker = Arc::new(Mutex::new(libraries.tracker()?));
+        trace_hooks.push(tracker.clone());
+        Some(tracker)
+    };
+    let profiler = if matches.is_present("PROFILE") {
+        let profiler = Arc::new(Mutex::new(Profiler::default()));
+        trace_hooks.push(profiler.clone());
+        Some(profiler)
+    } else {
+        None
     };
+
     let addr = MsgAddressInt::from_str(address)?;
-    let state_init = load_from_file(&input)?;
-    let config_cell_opt = matches.value_of("CONFIG").and_then(testcall::load_config);
-
-    let capabilities =
-        match config_cell_opt {
-            Some(ref config_cell) => {
-                let config_params = ConfigParams::with_address_and_root(
-                    UInt256::from_str(&"5".repeat(64)).unwrap(), // -1:5555...
-                    config_cell.clone());
-                config_params.capabilities()
-            }
-            None => {
-                DEFAULT_CAPABILITIES
+    let state_init = match &account {
+        Some(account) => account.state_init().cloned()
+            .ok_or_else(|| format_err!("account {} is not active", address))?,
+        None => load_from_file(&contract_file)?,
+    };
+    let config_cell_opt = matches.value_of("CONFIG").map(testcall::load_config).transpose()?;
+
+    let mut capabilities = testcall::get_capabilities(config_cell_opt.as_ref());
+    if let Some(spec) = matches.value_of("CAPABILITIES") {
+        capabilities = capabilities::apply_capabilities(capabilities, spec)?;
+    }
+    if matches.is_present("CHECK_REPLAY") {
+        let abi_contract = load_abi_contract(&load_abi_json_string(matches.value_of("ABI_JSON").unwrap())?)?;
+        let expired_msg = if abi_contract.header().iter().any(|param| param.name == "expire") {
+            let mut expired_header = header.unwrap_or_else(|| serde_json::json!({}));
+            expired_header["expire"] = now.saturating_sub(1).into();
+            let body = build_body(matches, Some(address.to_string()), Some(expired_header.to_string()))?;
+            Some(testcall::create_message(&addr, &MsgInfo { body, ..msg_info }, None)?)
+        } else {
+            None
+        };
+        let msg = testcall::create_message(&addr, &msg_info, None)?;
+        let (_, balance) = testcall::decode_balance(matches.value_of("BALANCE"))?;
+        let account = match account {
+            Some(mut account) => {
+                if matches.is_present("BALANCE") {
+                    account.set_balance(balance);
+                }
+                account
             }
+            None => Account::active_by_init_code_hash(addr, balance, now, state_init, false)?,
         };
-    let (_, state_init, is_success) = call_contract(addr, state_init, TestCallParams {
+        let config = testcall::blockchain_config(config_cell_opt, capabilities)?;
+        let report = replay::check_replay(account, msg, expired_msg, config, now, &accounts_dir(&contract_file))?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", replay::replay_printer(&report));
+        }
+        return Ok(())
+    }
+    let last_paid = matches.value_of("LAST_PAID")
+        .map(|v| v.parse::<u32>())
+        .transpose()
+        .map_err(|e| format_err!("failed to parse \"last-paid\" option: {}", e))?;
+    if msg_info.bounced {
+        msg_info.body = testcall::bounced_body(msg_info.body.take(), capabilities)?;
+    }
+    let c7 = parse_c7_options(matches)?;
+    let params = TestCallParams {
         balance: matches.value_of("BALANCE"),
         msg_info,
         config: config_cell_opt,
         key_file: sign,
         ticktock,
         gas_limit,
-        action_decoder: if matches.is_present("DECODEC6") { Some(action_decoder) } else { None },
+        action_decoder: if matches.is_present("DECODEC6") || json || getter { Some(action_decoder) } else { None },
         trace_level,
-        debug_info: testcall::load_debug_info(&debug_map_filename.unwrap_or("".to_string())),
-        capabilities
-    })?;
-    if is_success {
-        save_to_file(state_init, Some(&input), 0, false)?;
-        println!("Contract persistent data updated");
+        debug_info,
+        capabilities,
+        last_paid,
+        deliver: matches.is_present("DELIVER"),
+        dry_run: matches.is_present("DRY_RUN"),
+        json,
+        get_method,
+        getter,
+        check_accept: matches.is_present("CHECK_ACCEPT"),
+        c7,
+        libraries: libraries.dict(),
+        trace_hooks,
+        account: account.clone(),
+        deploy,
+        exit_codes: match matches.value_of("ERROR_CODES") {
+            Some(filename) => ExitCodes::with_file(filename)?,
+            None => ExitCodes::default(),
+        },
+        accounts_dir: accounts_dir(&contract_file),
+    };
+    let output = matches.value_of("OUTPUT_STATE").unwrap_or(&contract_file).to_string();
+    // the input state is recorded before the run, it is version 0 of the output file
+    let history = if matches.is_present("HISTORY") && !getter {
+        let history = History::new(&output);
+        history.start(&contract_file)?;
+        Some(history)
+    } else {
+        None
+    };
+    let (mut result, state_init) = if matches.is_present("TRANSACTION") {
+        execute_transaction(addr, state_init, params)?
+    } else {
+        call_contract(addr, state_init, params)?
+    };
+    if getter {
+        if !json {
+            println!("Getter output:");
+            for output in &result.outputs {
+                println!("{}", output);
+            }
+        }
+    } else if result.success && !matches.is_present("DRY_RUN") {
+        match account {
+            Some(mut account) => {
+                // computing phase only changes code and data of the account
+                let account = result.account.take().unwrap_or_else(|| {
+                    if let Some(code) = state_init.code {
+                        account.set_code(code);
+                    }
+                    if let Some(data) = state_init.data {
+                        account.set_data(data);
+                    }
+                    account
+                });
+                save_account_to_file(&account, &output)?;
+            }
+            None => {
+                save_to_file(state_init, Some(output.as_str()), 0, false)?;
+            }
+        }
+        if !json {
+            if output == contract_file {
+                println!("Contract persistent data updated");
+            } else {
+                println!("Contract state saved to {}", output);
+            }
+        }
+        if let Some(history) = history {
+            let version = history.record()?;
+            if !json {
+                println!("State version {} recorded", version);
+            }
+        }
+    }
+
+    if let (Some(coverage), Some(filename)) = (coverage, matches.value_of("COVERAGE")) {
+        save_coverage(&mut coverage.lock().unwrap(), filename, json)?;
+    }
+    if let Some(tracker) = library_tracker {
+        if !json {
+            print!("{}", tracker.lock().unwrap().report());
+        }
+    }
+    if let Some(profiler) = profiler {
+        let profiler = profiler.lock().unwrap();
+        println!("Gas profile:\n{}", profiler.report());
+        if let Some(filename) = matches.value_of("FOLDED") {
+            profiler.save_folded_stacks(filename)?;
+        }
     }
 
-    println!("TEST COMPLETED");
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        println!("TEST COMPLETED");
+    }
     Ok(())
 }
 
-fn build_body(matches: &ArgMatches, address: Option<String>) -> Result<Option<SliceData>> {
+fn run_config_subcmd(matches: &ArgMatches) -> Status {
+    let params = matches.value_of("PATCH").map(|patch| -> Result<serde_json::Map<String, serde_json::Value>> {
+        let patch = if patch.trim_start().starts_with('{') {
+            patch.to_owned()
+        } else {
+            std::fs::read_to_string(patch)
+                .map_err(|e| format_err!("failed to load config patch from file: {}", e))?
+        };
+        match serde_json::from_str(&patch)? {
+            serde_json::Value::Object(params) => Ok(params),
+            _ => bail!("config patch must be a JSON object"),
+        }
+    }).transpose()?;
+    let parse_flags = |name: &str| -> Result<u64> {
+        matches.values_of(name).map_or(Ok(0), |values| {
+            values.map(capabilities::parse_capability).try_fold(0, |mask, flag| -> Result<u64> { Ok(mask | flag?) })
+        })
+    };
+    let patch = config::ConfigPatch {
+        params,
+        capabilities: matches.value_of("CAPABILITIES").map(str::to_string),
+        enable: parse_flags("ENABLE")?,
+        disable: parse_flags("DISABLE")?,
+    };
+    config::config_command(
+        matches.value_of("BASE").unwrap(),
+        patch,
+        matches.value_of("OUTPUT"),
+    )
+}
+
+fn parse_c7_options(matches: &ArgMatches) -> Result<testcall::C7Options> {
+    let mut c7 = match matches.value_of("C7") {
+        Some(filename) => testcall::load_c7_options(filename)?,
+        None => testcall::C7Options::default(),
+    };
+    let parse_u64 = |name: &str| matches.value_of(name)
+        .map(|v| v.parse::<u64>().map_err(|e| format_err!("failed to parse {} option: {}", name, e)))
+        .transpose();
+    c7.rand_seed = matches.value_of("RAND_SEED").map(str::to_string).or(c7.rand_seed);
+    c7.init_code_hash = matches.value_of("INIT_CODE_HASH").map(str::to_string).or(c7.init_code_hash);
+    c7.block_lt = parse_u64("BLOCK_LT")?.or(c7.block_lt);
+    c7.trans_lt = parse_u64("TRANS_LT")?.or(c7.trans_lt);
+    c7.storage_fees = parse_u64("STORAGE_FEES")?.or(c7.storage_fees);
+    Ok(c7)
+}
+
+fn save_coverage(coverage: &mut Coverage, filename: &str, quiet: bool) -> Status {
+    let (hit, found) = coverage.summary();
+    coverage.save_lcov(filename)?;
+    if !quiet {
+        println!("Coverage: {} of {} lines executed, saved to {}", hit, found, filename);
+    }
+    Ok(())
+}
+
+fn run_scenario_subcmd(matches: &ArgMatches) -> Status {
+    let debug_maps: Vec<&str> = matches.values_of("DEBUG_MAP").map(Iterator::collect).unwrap_or_default();
+    let debug_info = testcall::load_debug_maps(&debug_maps)?;
+    let mut trace_hooks: Vec<SharedTraceHook> = vec![];
+    let coverage = match matches.value_of("COVERAGE") {
+        Some(_) => {
+            let coverage = Arc::new(Mutex::new(Coverage::with_debug_info(debug_info.as_ref())?));
+            trace_hooks.push(coverage.clone());
+            Some(coverage)
+        }
+        None => None
+    };
+    let result = scenario::run_scenario(matches.value_of("INPUT").unwrap(), debug_info, trace_hooks);
+    if let (Some(coverage), Some(filename)) = (coverage, matches.value_of("COVERAGE")) {
+        save_coverage(&mut coverage.lock().unwrap(), filename, false)?;
+    }
+    result
+}
+
+/// Returns ABI header supplied with --abi-header updated with `time` and `expire` options.
+fn abi_header(matches: &ArgMatches, now: u32) -> Result<Option<serde_json::Value>> {
+    let time = matches.value_of("MSG_TIME");
+    let expire = matches.value_of("EXPIRE");
+    let mut header = match matches.value_of("ABI_HEADER") {
+        Some(header) => serde_json::from_str(header)
+            .map_err(|e| format_err!("failed to parse \"abi-header\" option: {}", e))?,
+        None if time.is_none() && expire.is_none() => return Ok(None),
+        None => serde_json::json!({}),
+    };
+    if !header.is_object() {
+        bail!("ABI header must be a JSON object")
+    }
+    if let Some(time) = time {
+        header["time"] = testcall::parse_relative_time(time, now, 1000)?.into();
+    }
+    if let Some(expire) = expire {
+        header["expire"] = testcall::parse_relative_time(expire, now, 1)?.into();
+    }
+    Ok(Some(header))
+}
+
+fn build_body(matches: &ArgMatches, address: Option<String>, header: Option<String>) -> Result<Option<SliceData>> {
     let mut mask = 0u8;
     let abi_file = matches.value_of("ABI_JSON").map(|m| { mask |= 1; m });
     let method_name = matches.value_of("ABI_METHOD").map(|m| { mask |= 2; m });
     let params = matches.value_of("ABI_PARAMS");
-    let header = matches.value_of("ABI_HEADER");
     if mask == 0x3 {
         let key_file = matches.value_of("SIGN")
             .map(Keypair::from_file)
@@ -362,7 +838,7 @@ fn build_body(matches: &ArgMatches, address: Option<String>) -> Result<Option<Sl
             abi_file.unwrap(),
             method_name.unwrap(),
             &params,
-            header,
+            header.as_deref(),
             key_file,
             is_internal,
             address,
@@ -13,7 +13,7 @@
 use failure::format_err;
 use ton_block::*;
 use ton_types::write_boc;
-use ton_types::{BuilderData, Cell, Result};
+use ton_types::{BuilderData, Cell, Result, UInt256};
 
 fn get_version(root: &Cell) -> Result<String> {
     let cell1 = root.reference(0).map_err(|e| format_err!("not found ({})", e))?;
@@ -63,6 +63,44 @@ pub fn tree_of_cells_into_base64(root_cell: Option<&Cell>) -> String {
     }
 }
 
+/// Prints a pseudo-graphical tree of cells, repeated cells are printed only once.
+pub fn tree_of_cells_printer(root: &Cell) -> String {
+    let mut result = String::new();
+    let mut visited = std::collections::HashSet::new();
+    print_tree_of_cells(root, "", true, &mut visited, &mut result);
+    result += &format!("Total unique cells: {}\n", visited.len());
+    result
+}
+
+fn print_tree_of_cells(
+    cell: &Cell,
+    prefix: &str,
+    last: bool,
+    visited: &mut std::collections::HashSet<UInt256>,
+    result: &mut String,
+) {
+    let hash = cell.repr_hash();
+    let repeated = !visited.insert(hash.clone());
+    *result += &format!("{}{} {}b {}r {}{}\n",
+        prefix,
+        if last { "└─" } else { "├─" },
+        cell.bit_length(),
+        cell.references_count(),
+        cell.to_hex_string(),
+        if repeated { format!(" (repeated {})", hash.to_hex_string()) } else { String::new() },
+    );
+    if repeated {
+        return
+    }
+    let prefix = format!("{}{}", prefix, if last { "  " } else { "│ " });
+    let count = cell.references_count();
+    for i in 0..count {
+        if let Ok(child) = cell.reference(i) {
+            print_tree_of_cells(&child, &prefix, i + 1 == count, visited, result);
+        }
+    }
+}
+
 pub fn msg_printer(msg: &Message) -> Result<String> {
     let mut b = BuilderData::new();
     msg.write_to(&mut b)?;
@@ -88,6 +126,86 @@ pub fn msg_printer(msg: &Message) -> Result<String> {
     ))
 }
 
+pub fn transaction_printer(transaction: &Transaction) -> Result<String> {
+    let mut result = format!("Transaction\n lt: {}\n now: {}\n total_fees: {}\n out_msgs: {}\n",
+        transaction.logical_time(),
+        transaction.now(),
+        print_cc(transaction.total_fees()),
+        transaction.msg_count(),
+    );
+    match transaction.read_description()? {
+        TransactionDescr::Ordinary(descr) => {
+            result += &format!(" credit_first: {}\n", descr.credit_first);
+            result += &match descr.storage_ph {
+                Some(ph) => format!("Storage phase\n   fees_collected: {}\n   fees_due    : {}\n   status_change: {:?}\n",
+                    print_grams(&ph.storage_fees_collected),
+                    ph.storage_fees_due.as_ref().map_or("None".to_string(), print_grams),
+                    ph.status_change,
+                ),
+                None => "Storage phase: None\n".to_string(),
+            };
+            result += &match descr.credit_ph {
+                Some(ph) => format!("Credit phase\n   due_fees_collected: {}\n   credit      : {}\n",
+                    ph.due_fees_collected.as_ref().map_or("None".to_string(), print_grams),
+                    print_cc(&ph.credit),
+                ),
+                None => "Credit phase: None\n".to_string(),
+            };
+            result += &match descr.compute_ph {
+                TrComputePhase::Vm(ph) => format!("Compute phase\n   success     : {}\n   exit_code   : {}\n   gas_used    : {}\n   gas_limit   : {}\n   gas_credit  : {}\n   gas_fees    : {}\n   vm_steps    : {}\n   account_activated: {}\n",
+                    ph.success,
+                    ph.exit_code,
+                    ph.gas_used,
+                    ph.gas_limit,
+                    ph.gas_credit.map_or("None".to_string(), |credit| credit.to_string()),
+                    print_grams(&ph.gas_fees),
+                    ph.vm_steps,
+                    ph.account_activated,
+                ),
+                TrComputePhase::Skipped(ph) => format!("Compute phase: skipped ({:?})\n", ph.reason),
+            };
+            result += &match descr.action {
+                Some(ph) => format!("Action phase\n   success     : {}\n   valid       : {}\n   no_funds    : {}\n   result_code : {}\n   tot_actions : {}\n   msgs_created: {}\n   fwd_fees    : {}\n   action_fees : {}\n",
+                    ph.success,
+                    ph.valid,
+                    ph.no_funds,
+                    ph.result_code,
+                    ph.tot_actions,
+                    ph.msgs_created,
+                    ph.total_fwd_fees.as_ref().map_or("None".to_string(), print_grams),
+                    ph.total_action_fees.as_ref().map_or("None".to_string(), print_grams),
+                ),
+                None => "Action phase: None\n".to_string(),
+            };
+            result += &match descr.bounce {
+                Some(TrBouncePhase::Ok(ph)) => format!("Bounce phase\n   msg_fees    : {}\n   fwd_fees    : {}\n",
+                    print_grams(&ph.msg_fees),
+                    print_grams(&ph.fwd_fees),
+                ),
+                Some(TrBouncePhase::Nofunds(ph)) => format!("Bounce phase: no funds (required fwd fees {})\n",
+                    print_grams(&ph.req_fwd_fees),
+                ),
+                Some(TrBouncePhase::Negfunds) => "Bounce phase: negative funds\n".to_string(),
+                None => "Bounce phase: None\n".to_string(),
+            };
+            result += &format!("Aborted: {}\nDestroyed: {}\n", descr.aborted, descr.destroyed);
+        }
+        _ => result += "Non-ordinary transaction\n",
+    }
+    Ok(result)
+}
+
+pub fn account_printer(account: &Account) -> String {
+    format!("Account\n address: {}\n status: {:?}\n balance: {}\n last_paid: {}\n code_hash: {}\n data_hash: {}\n",
+        account.get_addr().map_or("None".to_string(), ToString::to_string),
+        account.status(),
+        account.balance().map_or("None".to_string(), print_cc),
+        account.last_paid(),
+        account.get_code().map(|code| code.repr_hash().to_hex_string()).unwrap_or_else(|| "None".to_string()),
+        account.get_data().map(|data| data.repr_hash().to_hex_string()).unwrap_or_else(|| "None".to_string()),
+    )
+}
+
 fn print_msg_header(header: &CommonMsgInfo) -> String {
     match header {
         CommonMsgInfo::IntMsgInfo(header) => {
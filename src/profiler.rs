@@ -0,0 +1,225 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use failure::format_err;
+use ton_labs_assembler::DbgPos;
+use ton_types::Status;
+use ton_vm::executor::{Engine, EngineTraceInfo, EngineTraceInfoType};
+
+use crate::callstack::{code_point, CallStack, CodePoint, Location};
+use crate::testcall::TraceHook;
+
+/// Entry point of the function, `None` for the outermost frame.
+type Function = Option<CodePoint>;
+
+#[derive(Default)]
+struct Counter {
+    gas: i64,
+    instructions: u64,
+}
+
+#[derive(Default)]
+struct FunctionCounter {
+    self_gas: i64,
+    total_gas: i64,
+    instructions: u64,
+}
+
+/// Interned call paths: every path is its parent path and the innermost function.
+#[derive(Default)]
+struct CallPaths {
+    paths: Vec<(Option<usize>, Function)>,
+    index: HashMap<(Option<usize>, Function), usize>,
+}
+
+impl CallPaths {
+    /// Returns index of the path of the functions from the outermost one.
+    fn intern<'a>(&mut self, functions: impl Iterator<Item = Option<&'a CodePoint>>) -> Option<usize> {
+        let mut parent = None;
+        for function in functions {
+            let key = (parent, function.cloned());
+            let index = match self.index.get(&key) {
+                Some(index) => *index,
+                None => {
+                    self.paths.push(key.clone());
+                    self.index.insert(key, self.paths.len() - 1);
+                    self.paths.len() - 1
+                }
+            };
+            parent = Some(index);
+        }
+        parent
+    }
+
+    /// Functions of the path from the outermost one.
+    fn functions(&self, mut path: Option<usize>) -> Vec<&Function> {
+        let mut functions = vec![];
+        while let Some(index) = path {
+            let (parent, function) = &self.paths[index];
+            functions.push(function);
+            path = *parent;
+        }
+        functions.reverse();
+        functions
+    }
+}
+
+/// Accumulates gas and instruction counts per instruction and per call path,
+/// the counters are grouped by source lines and function names in the report only.
+#[derive(Default)]
+pub struct Profiler {
+    call_stack: CallStack,
+    paths: CallPaths,
+    /// Source positions of the executed instructions
+    positions: HashMap<CodePoint, Option<DbgPos>>,
+    instructions: HashMap<CodePoint, Counter>,
+    /// Counters of the innermost functions of the paths by path index
+    path_counters: HashMap<usize, Counter>,
+}
+
+impl Profiler {
+    fn record(&mut self, path: Option<usize>, point: CodePoint, position: Option<&DbgPos>, gas: i64) {
+        let counter = self.instructions.entry(point.clone()).or_default();
+        counter.gas += gas;
+        counter.instructions += 1;
+        self.positions.entry(point).or_insert_with(|| position.cloned());
+        if let Some(path) = path {
+            let counter = self.path_counters.entry(path).or_default();
+            counter.gas += gas;
+            counter.instructions += 1;
+        }
+    }
+
+    fn location(&self, point: &CodePoint) -> String {
+        let position = self.positions.get(point).cloned().flatten();
+        Location { point: point.clone(), position }.to_string()
+    }
+
+    fn function_name(&self, function: &Function) -> String {
+        let position = function.as_ref().and_then(|point| self.positions.get(point)).and_then(Option::as_ref);
+        CallStack::function_name(function.as_ref(), position)
+    }
+
+    fn path_names(&self, path: usize) -> Vec<String> {
+        self.paths.functions(Some(path)).into_iter().map(|function| self.function_name(function)).collect()
+    }
+
+    fn lines(&self) -> HashMap<String, Counter> {
+        let mut lines = HashMap::<String, Counter>::new();
+        for (point, counter) in &self.instructions {
+            let line = lines.entry(self.location(point)).or_default();
+            line.gas += counter.gas;
+            line.instructions += counter.instructions;
+        }
+        lines
+    }
+
+    fn functions(&self) -> HashMap<String, FunctionCounter> {
+        let mut functions = HashMap::<String, FunctionCounter>::new();
+        for (path, counter) in &self.path_counters {
+            let names = self.path_names(*path);
+            let mut seen = HashSet::new();
+            for name in &names {
+                if seen.insert(name) {
+                    functions.entry(name.clone()).or_default().total_gas += counter.gas;
+                }
+            }
+            if let Some(name) = names.last() {
+                let function = functions.entry(name.clone()).or_default();
+                function.self_gas += counter.gas;
+                function.instructions += counter.instructions;
+            }
+        }
+        functions
+    }
+
+    pub fn report(&self) -> String {
+        let lines = self.lines();
+        let mut lines: Vec<_> = lines.iter().collect();
+        lines.sort_by(|a, b| b.1.gas.cmp(&a.1.gas).then(a.0.cmp(b.0)));
+        let functions = self.functions();
+        let mut functions: Vec<_> = functions.iter().collect();
+        functions.sort_by(|a, b| b.1.self_gas.cmp(&a.1.self_gas).then(a.0.cmp(b.0)));
+
+        let mut result = format!("{:>10} {:>8}  {}\n", "gas", "insns", "line");
+        for (location, counter) in lines {
+            result += &format!("{:>10} {:>8}  {}\n", counter.gas, counter.instructions, location);
+        }
+        result += &format!("\n{:>10} {:>10} {:>8}  {}\n", "self gas", "total gas", "insns", "function");
+        for (name, counter) in functions {
+            result += &format!("{:>10} {:>10} {:>8}  {}\n", counter.self_gas, counter.total_gas, counter.instructions, name);
+        }
+        result
+    }
+
+    /// Folded stacks format accepted by flamegraph.pl and inferno.
+    pub fn folded_stacks(&self) -> String {
+        let mut folded = BTreeMap::<String, i64>::new();
+        for (path, counter) in &self.path_counters {
+            *folded.entry(self.path_names(*path).join(";")).or_default() += counter.gas;
+        }
+        folded.iter()
+            .map(|(stack, gas)| format!("{} {}\n", stack, gas))
+            .collect()
+    }
+
+    pub fn save_folded_stacks(&self, filename: &str) -> Status {
+        std::fs::write(filename, self.folded_stacks())
+            .map_err(|e| format_err!("failed to write folded stacks {}: {}", filename, e))
+    }
+}
+
+impl TraceHook for Profiler {
+    fn on_step(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>) {
+        match info.info_type {
+            EngineTraceInfoType::Normal | EngineTraceInfoType::Implicit | EngineTraceInfoType::Exception => (),
+            _ => return,
+        }
+        self.call_stack.update(engine, info, position);
+        let path = self.paths.intern(self.call_stack.functions());
+        self.record(path, code_point(info), position, info.gas_cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_types::UInt256;
+
+    #[test]
+    fn test_profiler_aggregation() {
+        let point = |offset: usize| (UInt256::default(), offset);
+        let pos = |line: usize| DbgPos { filename: "a.sol".to_string(), line };
+        let mut profiler = Profiler::default();
+        let main = profiler.paths.intern([None].into_iter());
+        let function = point(100);
+        let nested = profiler.paths.intern([None, Some(&function)].into_iter());
+        assert_eq!(profiler.paths.intern([None].into_iter()), main);
+        profiler.record(main, point(0), Some(&pos(3)), 18);
+        profiler.record(nested, function.clone(), Some(&pos(10)), 26);
+        profiler.record(nested, point(110), Some(&pos(10)), 10);
+        profiler.record(main, point(8), None, 5);
+
+        let lines = profiler.lines();
+        assert_eq!(lines["a.sol:10"].gas, 36);
+        assert_eq!(lines["a.sol:10"].instructions, 2);
+        let functions = profiler.functions();
+        assert_eq!(functions["main"].self_gas, 23);
+        assert_eq!(functions["main"].total_gas, 59);
+        assert_eq!(functions["a.sol:10"].total_gas, 36);
+        assert_eq!(profiler.folded_stacks(), "main 23\nmain;a.sol:10 36\n");
+        assert!(profiler.report().lines().nth(1).unwrap().ends_with("a.sol:10"));
+        assert!(profiler.report().contains("0000000000000000:8"));
+    }
+}
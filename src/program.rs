@@ -76,6 +76,28 @@ pub fn load_from_file(contract_file: &str) -> Result<StateInit> {
     StateInit::construct_from_cell(cell)
 }
 
+/// Loads the account if the file contains an Account BOC rather than a StateInit.
+pub fn load_account_from_file(filename: &str) -> Result<Option<Account>> {
+    let bytes = std::fs::read(filename)
+        .map_err(|e| failure::format_err!("unable to read {}: {}", filename, e))?;
+    let cell = read_boc(bytes)?.roots.remove(0);
+    // account_none$0 and StateInit without split_depth both start with zero bit
+    if SliceData::load_cell_ref(&cell)?.get_next_bit()? {
+        if let Ok(account) = Account::construct_from_cell(cell) {
+            if !account.is_none() {
+                return Ok(Some(account))
+            }
+        }
+    }
+    Ok(None)
+}
+
+pub fn save_account_to_file(account: &Account, filename: &str) -> Result<()> {
+    std::fs::write(filename, account.write_to_bytes()?)
+        .map_err(|e| failure::format_err!("unable to write {}: {}", filename, e))?;
+    Ok(())
+}
+
 pub fn load_stateinit(file_name: &str) -> Result<(SliceData, Vec<u8>)> {
     let mut orig_bytes = Vec::new();
     let mut f = File::open(file_name)?;
@@ -104,4 +126,19 @@ mod tests {
         let addr = calc_userfriendly_address(-1, &addr, true, true);
         assert_eq!(addr, "kf/8uRo6OBbQ97jCx2EIuKm8Wmt6Vb15+KsQHFLbKSMiYIny");
     }
+
+    #[test]
+    fn test_load_account() {
+        let state = StateInit::default();
+        let addr = MsgAddressInt::with_standart(None, 0, SliceData::from_raw(vec![0x11; 32], 256)).unwrap();
+        let account = Account::active_by_init_code_hash(addr, CurrencyCollection::with_grams(100), 0, state.clone(), false).unwrap();
+        let dir = std::env::temp_dir();
+        let account_file = dir.join("tvm_linker_test_account.boc").to_string_lossy().to_string();
+        save_account_to_file(&account, &account_file).unwrap();
+        assert_eq!(load_account_from_file(&account_file).unwrap(), Some(account));
+
+        let state_file = dir.join("tvm_linker_test_state.tvc").to_string_lossy().to_string();
+        std::fs::write(&state_file, state.write_to_bytes().unwrap()).unwrap();
+        assert_eq!(load_account_from_file(&state_file).unwrap(), None);
+    }
 }
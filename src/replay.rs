@@ -0,0 +1,234 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use failure::format_err;
+use serde::Serialize;
+use ton_block::{Account, Message};
+use ton_executor::{BlockchainConfig, ExecutorError};
+use ton_types::Result;
+
+use crate::sandbox::Sandbox;
+
+#[derive(Default, Serialize)]
+pub struct ReplayReport {
+    /// The message is accepted by the contract
+    pub accepted: bool,
+    /// The same message is accepted once more after the first transaction
+    pub replay_accepted: bool,
+    /// The message with `expire` header in the past is accepted, `None` if the ABI has no such header
+    pub expired_accepted: Option<bool>,
+    pub warnings: Vec<String>,
+}
+
+/// Executes the external message and returns false if the contract does not accept it.
+fn accepted(sandbox: &mut Sandbox, msg: &Message) -> Result<bool> {
+    match sandbox.execute(msg.clone(), false) {
+        Ok(_) => Ok(true),
+        Err(err) => match err.downcast_ref::<ExecutorError>() {
+            Some(ExecutorError::NoAcceptError(_, _)) => Ok(false),
+            _ => Err(err),
+        }
+    }
+}
+
+/// Checks replay protection of the contract: the message is executed twice on the account
+/// and the expired message is executed on the initial account state.
+pub fn check_replay(
+    account: Account,
+    msg: Message,
+    expired_msg: Option<Message>,
+    config: BlockchainConfig,
+    now: u32,
+    accounts_dir: &str,
+) -> Result<ReplayReport> {
+    let balance = account.balance().cloned().unwrap_or_default();
+    let new_sandbox = |account: &Account| -> Result<Sandbox> {
+        let mut sandbox = Sandbox::new(accounts_dir, config.clone(), balance.clone(), now);
+        sandbox.insert_account(account.clone())?;
+        Ok(sandbox)
+    };
+
+    let mut report = ReplayReport::default();
+    let mut sandbox = new_sandbox(&account)?;
+    report.accepted = accepted(&mut sandbox, &msg)?;
+    if !report.accepted {
+        report.warnings.push("message is not accepted, replay protection cannot be checked".to_string());
+        return Ok(report)
+    }
+    report.replay_accepted = accepted(&mut sandbox, &msg)
+        .map_err(|e| format_err!("failed to execute the message twice: {}", e))?;
+    if report.replay_accepted {
+        report.warnings.push("the same message is accepted twice".to_string());
+    }
+    if let Some(expired_msg) = expired_msg {
+        let accepted = accepted(&mut new_sandbox(&account)?, &expired_msg)?;
+        if accepted {
+            report.warnings.push("expired message is accepted".to_string());
+        }
+        report.expired_accepted = Some(accepted);
+    }
+    Ok(report)
+}
+
+pub fn replay_printer(report: &ReplayReport) -> String {
+    let mut result = format!("Message accepted: {}\n", report.accepted);
+    if report.accepted {
+        result += &format!("Same message accepted twice: {}\n", report.replay_accepted);
+        result += &match report.expired_accepted {
+            Some(accepted) => format!("Expired message accepted: {}\n", accepted),
+            None => "Expired message is not checked: ABI has no expire header\n".to_string(),
+        };
+    }
+    for warning in &report.warnings {
+        result += &format!("WARNING: {}\n", warning);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_block::{CurrencyCollection, ExternalInboundMessageHeader, MsgAddressExt, MsgAddressInt, StateInit};
+    use ton_labs_assembler::compile_code;
+    use ton_types::{BuilderData, IBitstring, SliceData};
+
+    const NOW: u32 = 1_700_000_000;
+
+    /// Sequence number check: the message carries the number stored in the data, which is incremented.
+    const SEQNO_CODE: &str = "
+        DROP
+        LDU 32
+        DROP
+        PUSHROOT
+        CTOS
+        PLDU 32
+        OVER
+        EQUAL
+        THROWIFNOT 33
+        ACCEPT
+        INC
+        NEWC
+        STU 32
+        ENDC
+        POPROOT
+    ";
+
+    /// Expiration check of the `expire` field following the sequence number in the body.
+    const EXPIRE_CODE: &str = "
+        DROP
+        LDU 32
+        PLDU 32
+        NOW
+        GREATER
+        THROWIFNOT 57
+        PUSHROOT
+        CTOS
+        PLDU 32
+        OVER
+        EQUAL
+        THROWIFNOT 33
+        ACCEPT
+        INC
+        NEWC
+        STU 32
+        ENDC
+        POPROOT
+    ";
+
+    fn seqno(value: u32) -> BuilderData {
+        let mut builder = BuilderData::new();
+        builder.append_u32(value).unwrap();
+        builder
+    }
+
+    /// Body with the sequence number 0 and the expiration time, if any.
+    fn body(expire: Option<u32>) -> SliceData {
+        let mut body = seqno(0);
+        if let Some(expire) = expire {
+            body.append_u32(expire).unwrap();
+        }
+        SliceData::load_builder(body).unwrap()
+    }
+
+    fn check(code: &str) -> ReplayReport {
+        check_expired(code, None)
+    }
+
+    /// Checks the message expiring in a minute, the expired message has the expiration time in the past.
+    fn check_expired(code: &str, expire: Option<u32>) -> ReplayReport {
+        let addr = MsgAddressInt::with_standart(None, 0, [1; 32].into()).unwrap();
+        let state_init = StateInit {
+            code: Some(compile_code(code).unwrap().into_cell()),
+            data: Some(seqno(0).into_cell().unwrap()),
+            ..Default::default()
+        };
+        let balance = CurrencyCollection::with_grams(10_000_000_000);
+        let account = Account::active_by_init_code_hash(addr.clone(), balance, NOW, state_init, false).unwrap();
+        let msg = |body: SliceData| {
+            let mut msg = Message::with_ext_in_header(ExternalInboundMessageHeader {
+                src: MsgAddressExt::default(),
+                dst: addr.clone(),
+                import_fee: 0u64.into(),
+            });
+            msg.set_body(body);
+            msg
+        };
+        let expired_msg = expire.map(|expire| msg(body(Some(expire))));
+        let msg = msg(body(expire.map(|_| NOW + 60)));
+        check_replay(account, msg, expired_msg, BlockchainConfig::default(), NOW, ".").unwrap()
+    }
+
+    #[test]
+    fn test_check_replay() {
+        let report = check(SEQNO_CODE);
+        assert!(report.accepted && !report.replay_accepted);
+        assert!(report.warnings.is_empty());
+
+        let report = check("ACCEPT");
+        assert!(report.accepted && report.replay_accepted);
+        assert_eq!(report.warnings, vec!["the same message is accepted twice".to_string()]);
+
+        let report = check("THROW 100");
+        assert!(!report.accepted);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_check_expired() {
+        let report = check_expired(EXPIRE_CODE, Some(NOW - 1));
+        assert!(report.accepted && !report.replay_accepted);
+        assert_eq!(report.expired_accepted, Some(false));
+        assert!(report.warnings.is_empty());
+        assert!(replay_printer(&report).contains("Expired message accepted: false\n"));
+
+        let report = check_expired(SEQNO_CODE, Some(NOW - 1));
+        assert_eq!(report.expired_accepted, Some(true));
+        assert_eq!(report.warnings, vec!["expired message is accepted".to_string()]);
+        assert!(replay_printer(&report).ends_with("Expired message accepted: true\nWARNING: expired message is accepted\n"));
+    }
+
+    #[test]
+    fn test_replay_printer() {
+        let report = ReplayReport {
+            accepted: true,
+            replay_accepted: true,
+            expired_accepted: None,
+            warnings: vec!["the same message is accepted twice".to_string()],
+        };
+        assert_eq!(
+            replay_printer(&report),
+            "Message accepted: true\nSame message accepted twice: true\n\
+             Expired message is not checked: ABI has no expire header\n\
+             WARNING: the same message is accepted twice\n"
+        );
+    }
+}
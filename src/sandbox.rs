@@ -0,0 +1,328 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::AtomicU64},
+};
+
+use failure::{bail, format_err};
+use ton_block::{Account, CurrencyCollection, Message, MsgAddressInt, Transaction};
+use ton_executor::{BlockchainConfig, ExecuteParams, OrdinaryTransactionExecutor, TransactionExecutor};
+use ton_types::{HashmapE, Result, Status};
+use ton_vm::executor::{Engine, EngineTraceInfo};
+
+use crate::program::{load_account_from_file, load_from_file, save_account_to_file};
+
+const MAX_DELIVERED_MESSAGES: usize = 10000;
+const INITIAL_LT: u64 = 1_000_000;
+
+pub type TraceCallback = dyn Fn(&Engine, &EngineTraceInfo) + Send + Sync;
+
+/// Set of accounts stored in one directory: `<address>.boc` account files saved by the sandbox
+/// or `<address>.tvc` contract files with the default balance.
+/// Accounts are loaded on the first message addressed to them.
+pub struct Sandbox {
+    dir: PathBuf,
+    executor: OrdinaryTransactionExecutor,
+    accounts: HashMap<String, Account>,
+    updated: HashMap<String, MsgAddressInt>,
+    default_balance: CurrencyCollection,
+    now: u32,
+    lt: u64,
+    debug: bool,
+    trace_callback: Option<Arc<TraceCallback>>,
+    libraries: HashmapE,
+    max_transactions: usize,
+}
+
+/// Directory of the accounts for the input file: the directory the file is located in.
+pub fn accounts_dir(filename: &str) -> String {
+    match Path::new(filename).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+fn account_path(dir: &Path, addr: &MsgAddressInt, extension: &str) -> Result<String> {
+    let path = dir.join(format!("{}.{}", addr.address().to_hex_string(), extension));
+    path.to_str().map(str::to_string).ok_or_else(|| format_err!("invalid path {:?}", path))
+}
+
+fn load_account(dir: &Path, addr: &MsgAddressInt, balance: &CurrencyCollection, now: u32) -> Result<Account> {
+    let path = account_path(dir, addr, "boc")?;
+    if Path::new(&path).exists() {
+        return Ok(load_account_from_file(&path)?.unwrap_or_default())
+    }
+    let path = account_path(dir, addr, "tvc")?;
+    if !Path::new(&path).exists() {
+        return Ok(Account::default())
+    }
+    Account::active_by_init_code_hash(addr.clone(), balance.clone(), now, load_from_file(&path)?, false)
+}
+
+impl Sandbox {
+    pub fn new(dir: &str, config: BlockchainConfig, default_balance: CurrencyCollection, now: u32) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            executor: OrdinaryTransactionExecutor::new(config),
+            accounts: HashMap::new(),
+            updated: HashMap::new(),
+            default_balance,
+            now,
+            lt: INITIAL_LT,
+            debug: false,
+            trace_callback: None,
+            libraries: HashmapE::with_bit_len(256),
+            max_transactions: MAX_DELIVERED_MESSAGES,
+        }
+    }
+
+    pub fn set_trace_callback(&mut self, trace_callback: Option<Arc<TraceCallback>>) {
+        self.debug = trace_callback.is_some();
+        self.trace_callback = trace_callback;
+    }
+
+    /// Sets library cells resolved in all the transactions, as masterchain libraries do.
+    pub fn set_libraries(&mut self, libraries: HashmapE) {
+        self.libraries = libraries;
+    }
+
+    pub fn set_now(&mut self, now: u32) {
+        self.now = now;
+    }
+
+    pub fn insert_account(&mut self, account: Account) -> Status {
+        let addr = account.get_addr()
+            .ok_or_else(|| format_err!("cannot insert account without address"))?;
+        self.accounts.insert(addr.to_string(), account);
+        Ok(())
+    }
+
+    pub fn account(&mut self, addr: &MsgAddressInt) -> Result<&Account> {
+        let key = addr.to_string();
+        if !self.accounts.contains_key(&key) {
+            let account = load_account(&self.dir, addr, &self.default_balance, self.now)?;
+            self.accounts.insert(key.clone(), account);
+        }
+        Ok(&self.accounts[&key])
+    }
+
+    /// Executes the message and, if `deliver` is set, all the internal messages
+    /// produced by the resulting transactions in logical time order.
+    pub fn execute(&mut self, msg: Message, deliver: bool) -> Result<Vec<(MsgAddressInt, Transaction)>> {
+        let mut queue = BTreeMap::new();
+        let mut seq_no = 0u64;
+        queue.insert((0, seq_no), msg);
+        let mut transactions = vec![];
+        while let Some((_, msg)) = queue.pop_first() {
+            if transactions.len() >= self.max_transactions {
+                bail!("message queue is not exhausted after {} transactions", self.max_transactions)
+            }
+            let dst = msg.dst().ok_or_else(|| format_err!("message has no destination address"))?;
+            let transaction = self.execute_message(&msg, &dst)?;
+            if deliver {
+                transaction.iterate_out_msgs(|out_msg| {
+                    if let Some(header) = out_msg.int_header() {
+                        seq_no += 1;
+                        queue.insert((header.created_lt, seq_no), out_msg);
+                    }
+                    Ok(true)
+                })?;
+            }
+            transactions.push((dst, transaction));
+        }
+        Ok(transactions)
+    }
+
+    fn execute_message(&mut self, msg: &Message, dst: &MsgAddressInt) -> Result<Transaction> {
+        self.account(dst)?;
+        let key = dst.to_string();
+        let account = self.accounts.get_mut(&key).unwrap();
+        let params = ExecuteParams {
+            block_unixtime: self.now,
+            block_lt: self.lt,
+            last_tr_lt: Arc::new(AtomicU64::new(self.lt)),
+            debug: self.debug,
+            trace_callback: self.trace_callback.clone(),
+            state_libs: self.libraries.clone(),
+            ..ExecuteParams::default()
+        };
+        let transaction = self.executor.execute_with_params(Some(msg), account, params)?;
+        self.lt = transaction.logical_time() + transaction.msg_count() as u64 + 1;
+        if !transaction.read_description()?.is_aborted() {
+            self.updated.insert(key, dst.clone());
+        }
+        Ok(transaction)
+    }
+
+    /// Writes the accounts updated by successful transactions to `<address>.boc` files,
+    /// files of the deleted accounts are removed, `skip` account is left to the caller.
+    /// Returns addresses of the saved accounts.
+    pub fn save_accounts(&self, skip: Option<&MsgAddressInt>) -> Result<Vec<MsgAddressInt>> {
+        let mut saved = vec![];
+        for (key, addr) in &self.updated {
+            if Some(addr) == skip {
+                continue
+            }
+            let account = &self.accounts[key];
+            if account.is_none() {
+                for extension in ["boc", "tvc"] {
+                    let path = account_path(&self.dir, addr, extension)?;
+                    if Path::new(&path).exists() {
+                        std::fs::remove_file(&path)
+                            .map_err(|e| format_err!("unable to remove {}: {}", path, e))?;
+                    }
+                }
+                continue
+            }
+            save_account_to_file(account, &account_path(&self.dir, addr, "boc")?)?;
+            saved.push(addr.clone());
+        }
+        Ok(saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_block::{InternalMessageHeader, Serializable, StateInit};
+    use ton_labs_assembler::compile_code;
+    use ton_types::BuilderData;
+
+    use crate::program::save_to_file;
+    use crate::testcall::transaction_result;
+
+    const NOW: u32 = 1_700_000_000;
+    const TON: u64 = 1_000_000_000;
+
+    fn address(byte: u8) -> MsgAddressInt {
+        MsgAddressInt::with_standart(None, 0, [byte; 32].into()).unwrap()
+    }
+
+    /// Message sent by the contract, the source address is set by the executor.
+    fn outbound_msg(dst: &MsgAddressInt, value: u64) -> Message {
+        Message::with_int_header(InternalMessageHeader {
+            ihr_disabled: true,
+            dst: dst.clone(),
+            value: CurrencyCollection::with_grams(value),
+            ..Default::default()
+        })
+    }
+
+    fn inbound_msg(dst: &MsgAddressInt, value: u64) -> Message {
+        let hdr = InternalMessageHeader::with_addresses(address(0), dst.clone(), CurrencyCollection::with_grams(value));
+        Message::with_int_header(hdr)
+    }
+
+    /// Contract sending the messages stored in its data with the send mode on every inbound message.
+    fn sender_state(messages: &[Message], mode: u8) -> StateInit {
+        let send = format!("LDREF\nSWAP\nPUSHINT {}\nSENDRAWMSG\n", mode);
+        let code = format!("PUSHROOT\nCTOS\n{}ENDS\n", send.repeat(messages.len()));
+        let mut data = BuilderData::new();
+        for msg in messages {
+            data.checked_append_reference(msg.serialize().unwrap()).unwrap();
+        }
+        StateInit {
+            code: Some(compile_code(&code).unwrap().into_cell()),
+            data: Some(data.into_cell().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn sender(addr: &MsgAddressInt, messages: &[Message], balance: u64) -> Account {
+        let state_init = sender_state(messages, 1);
+        Account::active_by_init_code_hash(addr.clone(), CurrencyCollection::with_grams(balance), NOW, state_init, false).unwrap()
+    }
+
+    fn test_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    fn grams(account: &Account) -> u128 {
+        account.balance().unwrap().grams.as_u128()
+    }
+
+    #[test]
+    fn test_delivery() {
+        let dir = test_dir("tvm_linker_test_sandbox");
+        let (a, b, missing) = (address(1), address(2), address(3));
+        let messages = [outbound_msg(&b, TON), outbound_msg(&b, 2 * TON), outbound_msg(&missing, TON / 2)];
+        let mut sandbox = Sandbox::new(&dir, BlockchainConfig::default(), CurrencyCollection::with_grams(TON), NOW);
+        sandbox.insert_account(sender(&a, &messages, 10 * TON)).unwrap();
+        sandbox.insert_account(sender(&b, &[], TON)).unwrap();
+
+        let transactions = sandbox.execute(inbound_msg(&a, 10 * TON), true).unwrap();
+        let dsts: Vec<_> = transactions.iter().map(|(dst, _)| dst.clone()).collect();
+        assert_eq!(dsts, vec![a.clone(), b.clone(), b.clone(), missing.clone()]);
+        let values: Vec<_> = transactions.iter()
+            .map(|(_, tr)| tr.read_in_msg().unwrap().unwrap().get_value().unwrap().grams.as_u128())
+            .collect();
+        assert_eq!(values, vec![10 * TON as u128, TON as u128, 2 * TON as u128, TON as u128 / 2]);
+        assert!(transactions.windows(2).all(|pair| pair[0].1.logical_time() < pair[1].1.logical_time()));
+        // the message to the missing account is handled without the compute phase
+        assert_eq!(transaction_result(&transactions[3].1).unwrap(), (-1, false));
+
+        let fees_b: u128 = transactions[1..3].iter().map(|(_, tr)| tr.total_fees().grams.as_u128()).sum();
+        assert_eq!(grams(sandbox.account(&b).unwrap()), 4 * TON as u128 - fees_b);
+        let balance_a = grams(sandbox.account(&a).unwrap());
+        assert!(balance_a < 16_500_000_000 && balance_a > 16 * TON as u128);
+
+        let mut saved = sandbox.save_accounts(None).unwrap();
+        saved.sort_by_key(|addr| addr.to_string());
+        assert_eq!(saved, vec![a.clone(), b.clone(), missing.clone()]);
+        // whole accounts are saved: the balances and the uninitialized account are restored
+        let mut reloaded = Sandbox::new(&dir, BlockchainConfig::default(), CurrencyCollection::with_grams(TON), NOW);
+        for addr in [&a, &b, &missing] {
+            assert_eq!(reloaded.account(addr).unwrap(), sandbox.account(addr).unwrap());
+        }
+        assert!(!reloaded.account(&missing).unwrap().is_none());
+        assert!(reloaded.account(&address(4)).unwrap().is_none());
+        let mut saved = sandbox.save_accounts(Some(&a)).unwrap();
+        saved.sort_by_key(|addr| addr.to_string());
+        assert_eq!(saved, vec![b, missing]);
+    }
+
+    #[test]
+    fn test_deleted_account() {
+        let dir = test_dir("tvm_linker_test_sandbox_deleted");
+        let (a, b) = (address(1), address(2));
+        // sends the whole balance and deletes the account
+        let state_init = sender_state(&[outbound_msg(&b, 0)], 160);
+        let tvc = account_path(Path::new(&dir), &a, "tvc").unwrap();
+        save_to_file(state_init, Some(&tvc), 0, true).unwrap();
+        let mut sandbox = Sandbox::new(&dir, BlockchainConfig::default(), CurrencyCollection::with_grams(TON), NOW);
+        assert_eq!(grams(sandbox.account(&a).unwrap()), TON as u128);
+
+        sandbox.execute(inbound_msg(&a, TON), false).unwrap();
+        assert!(sandbox.account(&a).unwrap().is_none());
+        assert!(sandbox.save_accounts(None).unwrap().is_empty());
+        assert!(!Path::new(&tvc).exists());
+        assert_eq!(accounts_dir(&tvc), dir);
+        assert_eq!(accounts_dir("wallet.tvc"), ".");
+    }
+
+    #[test]
+    fn test_max_delivered_messages() {
+        let a = address(1);
+        let mut sandbox = Sandbox::new(".", BlockchainConfig::default(), CurrencyCollection::with_grams(TON), NOW);
+        sandbox.insert_account(sender(&a, &[outbound_msg(&a, TON / 100)], 10 * TON)).unwrap();
+        sandbox.max_transactions = 5;
+        assert_eq!(sandbox.execute(inbound_msg(&a, TON), false).unwrap().len(), 1);
+        let err = sandbox.execute(inbound_msg(&a, TON), true).unwrap_err();
+        assert!(err.to_string().contains("not exhausted after 5 transactions"));
+    }
+}
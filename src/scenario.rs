@@ -0,0 +1,379 @@
+/*
+ * Copyright 2018-2023 TON DEV SOLUTIONS LTD.
+ *
+ * Licensed under the SOFTWARE EVALUATION License (the "License"); you may not use
+ * this file except in compliance with the License.
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific TON DEV software governing permissions and
+ * limitations under the License.
+ */
+use std::{collections::BTreeMap, str::FromStr};
+
+use failure::{bail, format_err};
+use serde::Deserialize;
+use serde_json::Value;
+use ton_block::{Message, MsgAddressInt, Transaction};
+use ton_executor::ExecutorError;
+use ton_labs_assembler::DbgInfo;
+use ton_types::{AccountId, Result, SliceData, Status};
+
+use crate::abi::{build_abi_body, decode_body, sign_abi_body};
+use crate::capabilities::apply_capabilities;
+use crate::keyman::Keypair;
+use crate::program::get_now;
+use crate::sandbox::{accounts_dir, Sandbox};
+use crate::testcall::{
+    blockchain_config, bounced_body, create_message, create_trace_callback, decode_balance, get_capabilities,
+    load_config, transaction_result, MsgInfo, SharedTraceHook, TraceLevel,
+};
+
+fn default_deliver() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub config: Option<String>,
+    #[serde(default)]
+    pub capabilities: Option<Value>,
+    #[serde(default)]
+    pub now: Option<u32>,
+    #[serde(default)]
+    pub balance: Option<Value>,
+    #[serde(default = "default_deliver")]
+    pub deliver: bool,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub contract: String,
+    #[serde(default)]
+    pub abi: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub params: Option<Value>,
+    #[serde(default)]
+    pub header: Option<Value>,
+    #[serde(default)]
+    pub sign: Option<String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub internal: Option<Value>,
+    #[serde(default)]
+    pub src: Option<String>,
+    #[serde(default)]
+    pub bounced: bool,
+    #[serde(default)]
+    pub now: Option<u32>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub success: Option<bool>,
+    #[serde(default)]
+    pub output: Option<Value>,
+    #[serde(default)]
+    pub balances: BTreeMap<String, Value>,
+}
+
+pub fn load_scenario(filename: &str) -> Result<Scenario> {
+    let text = std::fs::read_to_string(filename)
+        .map_err(|e| format_err!("unable to read scenario file {}: {}", filename, e))?;
+    if filename.ends_with(".yaml") || filename.ends_with(".yml") {
+        serde_yaml::from_str(&text).map_err(|e| format_err!("cannot parse scenario: {}", e))
+    } else {
+        serde_json::from_str(&text).map_err(|e| format_err!("cannot parse scenario: {}", e))
+    }
+}
+
+/// Converts a JSON number or string to the string form used by CLI options.
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Parses `wc:hex` address or a bare hex address in the basechain.
+pub fn parse_address(address: &str) -> Result<MsgAddressInt> {
+    if address.contains(':') {
+        MsgAddressInt::from_str(address)
+    } else {
+        MsgAddressInt::with_standart(None, 0, AccountId::from_str(address)?)
+    }
+}
+
+fn build_step_message(step: &Step, addr: &MsgAddressInt, now: u32, capabilities: u64) -> Result<Message> {
+    let internal = step.internal.as_ref().map(value_to_string);
+    let is_internal = internal.is_some();
+    let body = match (&step.abi, &step.method, &step.body) {
+        (Some(abi), Some(method), None) => {
+            let params = step.params.as_ref().map_or("{}".to_string(), Value::to_string);
+            let header = step.header.as_ref().map(Value::to_string);
+            let keypair = step.sign.as_deref().map(Keypair::from_file).transpose()?;
+            let body = build_abi_body(
+                abi, method, &params, header.as_deref(), keypair, is_internal, Some(addr.to_string())
+            )?;
+            Some(SliceData::load_builder(body)?)
+        }
+        (_, _, Some(body)) => {
+            let buf = hex::decode(body)
+                .map_err(|e| format_err!("body {} is invalid hex string: {}", body, e))?;
+            let len = buf.len() * 8;
+            let body = SliceData::from_raw(buf, len);
+            match (&step.abi, &step.sign) {
+                (Some(abi), Some(sign)) if !is_internal => {
+                    let header = step.header.as_ref().map(Value::to_string);
+                    let address = addr.to_string();
+                    Some(sign_abi_body(abi, body, header.as_deref(), Keypair::from_file(sign)?, Some(&address))?)
+                }
+                _ => Some(body),
+            }
+        }
+        (None, None, None) => None,
+        _ => bail!("both abi and method must be supplied for ABI call"),
+    };
+    let body = if step.bounced {
+        bounced_body(body, capabilities)?
+    } else {
+        body
+    };
+    let msg_info = MsgInfo {
+        balance: internal.as_deref(),
+        src: step.src.as_deref(),
+        now,
+        bounced: step.bounced,
+        body,
+    };
+    let key_file = match (&step.abi, &step.sign, &step.body) {
+        // raw body is signed as the ABI requires
+        (Some(_), Some(_), Some(_)) if !is_internal => None,
+        (_, sign, Some(_)) => Some(sign.as_deref()),
+        _ => None,
+    };
+    create_message(addr, &msg_info, key_file)
+}
+
+fn decode_output(step: &Step, transaction: &Transaction) -> Result<Option<Value>> {
+    let (abi, method) = match (&step.abi, &step.method) {
+        (Some(abi), Some(method)) => (abi, method),
+        _ => return Ok(None),
+    };
+    let mut output = None;
+    transaction.iterate_out_msgs(|msg| {
+        if let Some(body) = msg.body() {
+            if let Ok(decoded) = decode_body(abi, method, body, msg.is_internal()) {
+                output = Some(serde_json::from_str(&decoded)?);
+                return Ok(false)
+            }
+        }
+        Ok(true)
+    })?;
+    Ok(output)
+}
+
+fn check_step(step: &Step, sandbox: &mut Sandbox, exit_code: i32, success: bool, output: Option<Value>) -> Result<Vec<String>> {
+    let mut failures = vec![];
+    if let Some(expected) = step.expect.exit_code {
+        if expected != exit_code {
+            failures.push(format!("exit code {}, expected {}", exit_code, expected));
+        }
+    }
+    if let Some(expected) = step.expect.success {
+        if expected != success {
+            failures.push(format!("success {}, expected {}", success, expected));
+        }
+    }
+    if let Some(expected) = &step.expect.output {
+        match output {
+            Some(output) if &output == expected => (),
+            Some(output) => failures.push(format!("output {}, expected {}", output, expected)),
+            None => failures.push(format!("no output, expected {}", expected)),
+        }
+    }
+    for (address, expected) in &step.expect.balances {
+        let expected = value_to_string(expected);
+        let account = sandbox.account(&parse_address(address)?)?;
+        let balance = account.balance().map_or("None".to_string(), |balance| balance.grams.to_string());
+        if balance != expected {
+            failures.push(format!("balance of {} is {}, expected {}", address, balance, expected));
+        }
+    }
+    Ok(failures)
+}
+
+fn run_step(step: &Step, sandbox: &mut Sandbox, now: u32, deliver: bool, capabilities: u64) -> Result<Vec<String>> {
+    let addr = parse_address(&step.contract)?;
+    let msg = build_step_message(step, &addr, now, capabilities)?;
+    sandbox.set_now(now);
+    let (exit_code, success, output) = match sandbox.execute(msg, deliver) {
+        Ok(transactions) => {
+            let (exit_code, success) = transaction_result(&transactions[0].1)?;
+            (exit_code, success, decode_output(step, &transactions[0].1)?)
+        }
+        Err(err) => match err.downcast_ref::<ExecutorError>() {
+            Some(ExecutorError::NoAcceptError(exit_code, _)) => (*exit_code, false, None),
+            _ => return Err(err),
+        }
+    };
+    check_step(step, sandbox, exit_code, success, output)
+}
+
+/// Name of the step and its unmet expectations, empty if the step has passed.
+pub struct StepResult {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+/// Executes the steps in one sandbox and reports every step, the accounts are saved at the end.
+fn execute_scenario(filename: &str, debug_info: Option<DbgInfo>, trace_hooks: Vec<SharedTraceHook>) -> Result<Vec<StepResult>> {
+    let scenario = load_scenario(filename)?;
+    let config = scenario.config.as_deref().map(load_config).transpose()?;
+    let mut capabilities = get_capabilities(config.as_ref());
+    if let Some(spec) = &scenario.capabilities {
+        capabilities = apply_capabilities(capabilities, &value_to_string(spec))?;
+    }
+    let balance = scenario.balance.as_ref().map(value_to_string);
+    let (_, balance) = decode_balance(balance.as_deref())?;
+    let now = scenario.now.unwrap_or_else(get_now);
+
+    let mut sandbox = Sandbox::new(&accounts_dir(filename), blockchain_config(config, capabilities)?, balance, now);
+    sandbox.set_trace_callback(create_trace_callback(TraceLevel::None, debug_info, trace_hooks));
+    let mut results = vec![];
+    for (i, step) in scenario.steps.iter().enumerate() {
+        let name = step.name.clone().unwrap_or_else(|| step.method.clone().unwrap_or_default());
+        let failures = run_step(step, &mut sandbox, step.now.unwrap_or(now), scenario.deliver, capabilities)?;
+        if failures.is_empty() {
+            println!("Step {} {}: PASSED", i + 1, name);
+        } else {
+            println!("Step {} {}: FAILED", i + 1, name);
+            for failure in &failures {
+                println!("  {}", failure);
+            }
+        }
+        results.push(StepResult { name, failures });
+    }
+    for saved in sandbox.save_accounts(None)? {
+        println!("Contract {} persistent data updated", saved);
+    }
+    Ok(results)
+}
+
+pub fn run_scenario(filename: &str, debug_info: Option<DbgInfo>, trace_hooks: Vec<SharedTraceHook>) -> Status {
+    let results = execute_scenario(filename, debug_info, trace_hooks)?;
+    let failed = results.iter().filter(|result| !result.failures.is_empty()).count();
+    println!("{} steps passed, {} failed", results.len() - failed, failed);
+    if failed != 0 {
+        bail!("scenario {} failed", filename)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ton_block::{Serializable, StateInit};
+    use ton_labs_assembler::compile_code;
+    use ton_types::{BuilderData, IBitstring};
+
+    use crate::program::{load_account_from_file, save_to_file};
+
+    /// Counter contract incrementing the value in c4 on every message, throws 100 on the third one.
+    fn counter_state() -> StateInit {
+        let code = "PUSHROOT\nCTOS\nLDU 32\nENDS\nINC\nDUP\nPUSHINT 3\nEQUAL\nTHROWIF 100\nNEWC\nSTU 32\nENDC\nPOPROOT\n";
+        let mut data = BuilderData::new();
+        data.append_u32(0).unwrap();
+        StateInit {
+            code: Some(compile_code(code).unwrap().into_cell()),
+            data: Some(data.into_cell().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_run_scenario() {
+        let dir = std::env::temp_dir().join("tvm_linker_test_scenario");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let state_init = counter_state();
+        let hash = state_init.hash().unwrap().to_hex_string();
+        save_to_file(state_init, Some(dir.join(format!("{}.tvc", hash)).to_str().unwrap()), 0, true).unwrap();
+        let scenario = dir.join("scenario.json").to_string_lossy().to_string();
+        std::fs::write(&scenario, format!(r#"{{
+            "now": 1700000000,
+            "deliver": false,
+            "steps": [
+                {{ "name": "first", "contract": "{0}", "internal": 1000000000, "expect": {{ "exit_code": 0, "success": true }} }},
+                {{ "name": "second", "contract": "{0}", "internal": 1000000000, "expect": {{ "exit_code": 1, "balances": {{ "{0}": "1" }} }} }},
+                {{ "name": "third", "contract": "{0}", "internal": 1000000000, "expect": {{ "exit_code": 100, "success": false }} }}
+            ]
+        }}"#, hash)).unwrap();
+
+        let results = execute_scenario(&scenario, None, vec![]).unwrap();
+        let names: Vec<_> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second", "third"]);
+        assert!(results[0].failures.is_empty());
+        assert_eq!(results[1].failures.len(), 2);
+        assert_eq!(results[1].failures[0], "exit code 0, expected 1");
+        assert!(results[1].failures[1].starts_with(&format!("balance of {} is ", hash)));
+        assert!(results[1].failures[1].ends_with(", expected 1"));
+        // the third message throws only if the counter is carried from the previous steps
+        assert!(results[2].failures.is_empty());
+        assert!(run_scenario(&scenario, None, vec![]).is_err());
+
+        // the second run continues from the saved counter 2 and fails, the failed transactions are not saved
+        let account = load_account_from_file(dir.join(format!("{}.boc", hash)).to_str().unwrap()).unwrap().unwrap();
+        let mut data = SliceData::load_cell(account.get_data().unwrap()).unwrap();
+        assert_eq!(data.get_next_u32().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_load_scenario_json() {
+        let scenario: Scenario = serde_json::from_str(r#"{
+            "now": 1700000000,
+            "steps": [
+                { "contract": "0:1111111111111111111111111111111111111111111111111111111111111111",
+                  "internal": 1000000000, "body": "00",
+                  "expect": { "exit_code": 0, "balances": { "0:1111111111111111111111111111111111111111111111111111111111111111": "100" } } }
+            ]
+        }"#).unwrap();
+        assert!(scenario.deliver);
+        assert_eq!(scenario.steps.len(), 1);
+        assert_eq!(scenario.steps[0].internal.as_ref().map(value_to_string), Some("1000000000".to_string()));
+        assert_eq!(scenario.steps[0].expect.exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_load_scenario_yaml() {
+        let scenario: Scenario = serde_yaml::from_str("
+deliver: false
+steps:
+  - contract: '2222222222222222222222222222222222222222222222222222222222222222'
+    abi: wallet.abi.json
+    method: getBalance
+    expect:
+      output: { balance: '100' }
+").unwrap();
+        assert!(!scenario.deliver);
+        assert_eq!(scenario.steps[0].method.as_deref(), Some("getBalance"));
+        let addr = parse_address(&scenario.steps[0].contract).unwrap();
+        assert_eq!(addr.workchain_id(), 0);
+    }
+}
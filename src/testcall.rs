@@ -11,11 +11,12 @@
  * limitations under the License.
  */
 
-use std::{fs::File, str::FromStr, sync::Arc};
+use std::{fs::File, str::FromStr, sync::{Arc, Mutex}};
 
-use failure::format_err;
+use failure::{bail, format_err};
 use log::{log_enabled, Level::Error};
 use simplelog::{SimpleLogger, Config, LevelFilter};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use ton_vm::{
     int,
@@ -24,19 +25,28 @@ use ton_vm::{
     stack::{StackItem, Stack, savelist::SaveList, integer::IntegerData},
     SmartContractInfo,
 };
-use ton_types::{AccountId, BuilderData, Cell, SliceData, Result, Status, HashmapE, ed25519_sign_with_secret};
+use ton_types::{
+    read_single_root_boc, BuilderData, Cell, IBitstring, SliceData, Result, Status, HashmapE, UInt256, ed25519_sign_with_secret
+};
 use ton_block::{
-    CurrencyCollection, Deserializable, ExternalInboundMessageHeader, Grams,
-    InternalMessageHeader, Message, MsgAddressExt, MsgAddressInt, OutAction,
-    OutActions, Serializable, StateInit,
+    Account, ConfigParam8, ConfigParamEnum, ConfigParams, CurrencyCollection, Deserializable,
+    ExternalInboundMessageHeader, GasLimitsPrices, GlobalCapabilities, Grams, InternalMessageHeader, Message,
+    MsgAddressExt, MsgAddressInt, OutAction, OutActions, Serializable, StateInit, TrComputePhase, Transaction,
+    TransactionDescr,
 };
-use ton_labs_assembler::DbgInfo;
+use ton_executor::{BlockchainConfig, ExecutorError};
+use ton_labs_assembler::{DbgInfo, DbgPos};
 
+use crate::acceptance::{acceptance_printer, AcceptanceChecker, AcceptanceReport};
+use crate::capabilities::capabilities_printer;
+use crate::exit_codes::{error_printer, ErrorInfo, ExceptionLocator, ExitCodes};
 use crate::keyman::Keypair;
-use crate::printer::msg_printer;
+use crate::printer::{account_printer, msg_printer, transaction_printer, tree_of_cells_into_base64};
 use crate::program::{load_from_file, get_now};
+use crate::sandbox::{Sandbox, TraceCallback};
 
-const DEFAULT_ACCOUNT_BALANCE: &str = "100000000000";
+pub const DEFAULT_ACCOUNT_BALANCE: &str = "100000000000";
+pub const DEFAULT_CAPABILITIES: u64 = 0x880116ae; // Default capabilities on the main network
 
 fn create_external_inbound_msg(src: MsgAddressExt, dst: MsgAddressInt, body: Option<SliceData>) -> Message {
     let hdr = ExternalInboundMessageHeader {
@@ -78,6 +88,29 @@ fn create_internal_msg(
     msg
 }
 
+/// Creates the body of the message bounced from the original one as the network does:
+/// the body is empty without `CapBounceMsgBody`, otherwise it is `0xFFFFFFFF` followed by
+/// the first 256 bits of the original body, with `CapFullBodyInBounced` the whole original body
+/// is added as a reference.
+pub fn bounced_body(original: Option<SliceData>, capabilities: u64) -> Result<Option<SliceData>> {
+    if capabilities & GlobalCapabilities::CapBounceMsgBody as u64 == 0 {
+        return Ok(None)
+    }
+    let mut builder = BuilderData::new();
+    builder.append_u32(0xFFFF_FFFF)?;
+    if let Some(original) = original {
+        let mut head = original.clone();
+        head.shrink_data(..head.remaining_bits().min(256));
+        builder.append_bytestring(&head)?;
+        if capabilities & GlobalCapabilities::CapFullBodyInBounced as u64 != 0 {
+            builder.checked_append_reference(original.as_builder().into_cell()?)?;
+        }
+    }
+    Ok(Some(SliceData::load_builder(builder)?))
+}
+
+/// Signs the body in ABI 1.0 layout: the reference with the signature of the body hash
+/// and the public key is prepended to the body.
 fn sign_body(body: &mut SliceData, key_file: Option<&str>) -> Status {
     let mut signed_body = body.as_builder();
     let mut sign_builder = BuilderData::new();
@@ -93,28 +126,152 @@ fn sign_body(body: &mut SliceData, key_file: Option<&str>) -> Status {
     Ok(())
 }
 
+/// Index of the previous blocks info in SmartContractInfo tuple. The tuple reaches it only when
+/// all the optional fields (code, init code hash, storage fees) are enabled by capabilities.
+const PREV_BLOCKS_INFO_INDEX: usize = 13;
+
+/// Blockchain parameters the SmartContractInfo tuple depends on.
+struct Network {
+    config_params: Option<Cell>,
+    capabilities: u64,
+}
+
+/// Optional values of SmartContractInfo tuple (c7) overriding the default ones.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct C7Options {
+    /// 256-bit hex string
+    #[serde(default)]
+    pub rand_seed: Option<String>,
+    #[serde(default)]
+    pub block_lt: Option<u64>,
+    #[serde(default)]
+    pub trans_lt: Option<u64>,
+    /// 256-bit hex string, code hash by default
+    #[serde(default)]
+    pub init_code_hash: Option<String>,
+    #[serde(default)]
+    pub storage_fees: Option<u64>,
+    /// Stack item in JSON form, see `json_to_stack_item`
+    #[serde(default)]
+    pub prev_blocks_info: Option<Value>,
+    /// Whole SmartContractInfo tuple replacing the one built from the options
+    #[serde(default)]
+    pub tuple: Option<Vec<Value>>,
+}
+
+pub fn load_c7_options(filename: &str) -> Result<C7Options> {
+    let text = std::fs::read_to_string(filename)
+        .map_err(|e| format_err!("unable to read c7 file {}: {}", filename, e))?;
+    serde_json::from_str(&text).map_err(|e| format_err!("cannot parse c7 file {}: {}", filename, e))
+}
+
+fn parse_uint256(value: &str) -> Result<UInt256> {
+    let value = value.trim_start_matches("0x");
+    UInt256::from_str(&format!("{:0>64}", value))
+        .map_err(|e| format_err!("invalid 256-bit hex value {}: {}", value, e))
+}
+
+/// Parses an integer from a JSON number or a decimal or `0x`-prefixed hex string.
+fn parse_integer(value: &Value) -> Result<IntegerData> {
+    match value {
+        Value::Number(n) => IntegerData::from_str_radix(&n.to_string(), 10),
+        Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) => IntegerData::from_str_radix(hex, 16),
+            None => match s.strip_prefix("-0x") {
+                Some(hex) => IntegerData::from_str_radix(&format!("-{}", hex), 16),
+                None => IntegerData::from_str_radix(s, 10),
+            }
+        },
+        _ => bail!("invalid integer {}", value),
+    }
+}
+
+/// Converts JSON to a stack item: `null`, integer (number or string), array (tuple),
+/// `{"cell": "<base64 boc>"}` or `{"slice": "<base64 boc>"}`.
+pub fn json_to_stack_item(value: &Value) -> Result<StackItem> {
+    let load_cell = |boc: &Value| -> Result<Cell> {
+        let boc = boc.as_str().ok_or_else(|| format_err!("BOC must be a base64 string"))?;
+        let bytes = base64::decode(boc).map_err(|e| format_err!("invalid base64 BOC: {}", e))?;
+        read_single_root_boc(bytes)
+    };
+    Ok(match value {
+        Value::Null => StackItem::None,
+        Value::Number(_) | Value::String(_) => StackItem::Integer(Arc::new(parse_integer(value)?)),
+        Value::Array(items) => StackItem::tuple(items.iter().map(json_to_stack_item).collect::<Result<Vec<_>>>()?),
+        Value::Object(map) => match (map.get("cell"), map.get("slice")) {
+            (Some(boc), None) => StackItem::Cell(load_cell(boc)?),
+            (None, Some(boc)) => StackItem::Slice(SliceData::load_cell(load_cell(boc)?)?),
+            _ => bail!("invalid stack item {}", value),
+        }
+        _ => bail!("invalid stack item {}", value),
+    })
+}
+
 fn initialize_registers(
     data: SliceData,
     mycode: Cell,
     myself: MsgAddressInt,
     unix_time: u32,
     balance: CurrencyCollection,
-    config_params: Option<Cell>,
-    capabilities: u64,
+    network: Network,
+    c7: &C7Options,
 ) -> Result<SaveList> {
     let mut ctrls = SaveList::new();
-    let info = SmartContractInfo {
-        capabilities,
+    let init_code_hash = match &c7.init_code_hash {
+        Some(hash) => parse_uint256(hash)?,
+        None => mycode.repr_hash(),
+    };
+    let mut info = SmartContractInfo {
+        capabilities: network.capabilities,
         balance,
         myself: SliceData::load_cell(myself.serialize()?).unwrap(),
         mycode,
         unix_time,
-        config_params,
+        config_params: network.config_params,
         ..Default::default()
     };
-    // TODO info.set_init_code_hash()
+    info.set_init_code_hash(init_code_hash);
+    if let Some(rand_seed) = &c7.rand_seed {
+        info.rand_seed = parse_uint256(rand_seed)?;
+    }
+    if let Some(block_lt) = c7.block_lt {
+        info.block_lt = block_lt;
+    }
+    if let Some(trans_lt) = c7.trans_lt {
+        info.trans_lt = trans_lt;
+    }
+    if let Some(storage_fees) = c7.storage_fees {
+        info.set_storage_fee(storage_fees as u128);
+    }
+    let mut params = match &c7.tuple {
+        Some(items) => items.iter().map(json_to_stack_item).collect::<Result<Vec<_>>>()?,
+        None => match info.into_temp_data_item() {
+            StackItem::Tuple(c7) => match c7.first() {
+                Some(StackItem::Tuple(params)) => params.to_vec(),
+                _ => bail!("unexpected SmartContractInfo layout"),
+            }
+            _ => bail!("unexpected SmartContractInfo layout"),
+        }
+    };
+    if let Some(prev_blocks_info) = &c7.prev_blocks_info {
+        // padding a shorter tuple would put the info into the slots of other fields
+        if params.len() < PREV_BLOCKS_INFO_INDEX {
+            bail!(
+                "SmartContractInfo tuple has {} items, previous blocks info needs {}: enable CapMycode, \
+                CapInitCodeHash and CapStorageFeeToTvm capabilities or supply the whole tuple",
+                params.len(), PREV_BLOCKS_INFO_INDEX
+            )
+        }
+        let prev_blocks_info = json_to_stack_item(prev_blocks_info)?;
+        if params.len() == PREV_BLOCKS_INFO_INDEX {
+            params.push(prev_blocks_info);
+        } else {
+            params[PREV_BLOCKS_INFO_INDEX] = prev_blocks_info;
+        }
+    }
     ctrls.put(4, &mut StackItem::Cell(data.into_cell()))?;
-    ctrls.put(7, &mut info.into_temp_data_item())?;
+    ctrls.put(7, &mut StackItem::tuple(vec![StackItem::tuple(params)]))?;
     Ok(ctrls)
 }
 
@@ -130,7 +287,7 @@ fn init_logger(debug: bool) -> Status {
 fn create_inbound_msg(
     selector: i32,
     msg_info: &MsgInfo,
-    dst: AccountId,
+    dst: MsgAddressInt,
 ) -> Result<Option<Message>> {
     let (_, value) = decode_balance(msg_info.balance)?;
     Ok(match selector {
@@ -141,7 +298,7 @@ fn create_inbound_msg(
             };
             Some(create_internal_msg(
                 src,
-                MsgAddressInt::with_standart(None, 0, dst)?,
+                dst,
                 value,
                 1,
                 get_now(),
@@ -160,8 +317,7 @@ fn create_inbound_msg(
             };
             Some(create_external_inbound_msg(
                 src,
-                MsgAddressInt::with_standart(None, 0, dst)
-                    .map_err(|e| format_err!("Failed to convert address: {}", e))?,
+                dst,
                 msg_info.body.clone(),
             ))
         },
@@ -169,31 +325,69 @@ fn create_inbound_msg(
     })
 }
 
-fn decode_actions<F>(actions: StackItem, state: &mut StateInit, action_decoder: F) -> Status
-    where F: Fn(SliceData, bool)
+fn out_action_info<F>(
+    action: &str,
+    mode: Option<u8>,
+    out_msg: Option<&Message>,
+    action_decoder: Option<&F>,
+    result: &mut TestCallResult,
+    verbose: bool,
+) -> Status
+    where F: Fn(SliceData, bool) -> Option<String>
+{
+    let mut info = OutActionInfo { action: action.to_string(), mode, ..Default::default() };
+    if let Some(out_msg) = out_msg {
+        if verbose {
+            println!("Action({}):\n{}", action, msg_printer(out_msg)?);
+        }
+        info.message = Some(tree_of_cells_into_base64(Some(&out_msg.serialize()?)));
+        if let (Some(body), Some(decoder)) = (out_msg.body(), action_decoder) {
+            if let Some(output) = decoder(body, out_msg.is_internal()) {
+                if verbose {
+                    println!("{}", output);
+                }
+                let output = serde_json::from_str(&output).unwrap_or(Value::String(output));
+                info.output = Some(output.clone());
+                result.outputs.push(output);
+            }
+        }
+    } else if verbose {
+        println!("Action({})", action);
+    }
+    result.actions.push(info);
+    Ok(())
+}
+
+fn decode_actions<F>(
+    actions: StackItem,
+    state: &mut StateInit,
+    action_decoder: Option<&F>,
+    result: &mut TestCallResult,
+    verbose: bool,
+) -> Status
+    where F: Fn(SliceData, bool) -> Option<String>
 {
     if let StackItem::Cell(cell) = &actions {
         let actions: OutActions = OutActions::construct_from(&mut SliceData::load_cell_ref(cell)?)?;
-        println!("Output actions:\n----------------");
+        if verbose {
+            println!("Output actions:\n----------------");
+        }
         for act in actions {
             match act {
-                OutAction::SendMsg{mode: _, out_msg } => {
-                    println!("Action(SendMsg):\n{}", msg_printer(&out_msg)?);
-                    if let Some(b) = out_msg.body() {
-                        action_decoder(b, out_msg.is_internal());
-                    }
+                OutAction::SendMsg{ mode, out_msg } => {
+                    out_action_info("SendMsg", Some(mode), Some(&out_msg), action_decoder, result, verbose)?;
                 },
                 OutAction::SetCode{ new_code: code } => {
-                    println!("Action(SetCode)");
+                    out_action_info("SetCode", None, None, action_decoder, result, verbose)?;
                     state.code = Some(code);
                 },
-                OutAction::ReserveCurrency { .. } => {
-                    println!("Action(ReserveCurrency)");
+                OutAction::ReserveCurrency { mode, .. } => {
+                    out_action_info("ReserveCurrency", Some(mode), None, action_decoder, result, verbose)?;
                 },
-                OutAction::ChangeLibrary { .. } => {
-                    println!("Action(ChangeLibrary)");
+                OutAction::ChangeLibrary { mode, .. } => {
+                    out_action_info("ChangeLibrary", Some(mode), None, action_decoder, result, verbose)?;
                 },
-                _ => println!("Action(Unknown)"),
+                _ => out_action_info("Unknown", None, None, action_decoder, result, verbose)?,
             };
         }
     }
@@ -206,7 +400,15 @@ pub fn load_code_and_data(state_init: &StateInit) -> (SliceData, SliceData) {
     (code, data)
 }
 
-fn decode_balance(value: Option<&str>) -> Result<(u64, CurrencyCollection)> {
+/// Takes the balance of the supplied account unless the balance is given explicitly.
+fn contract_balance(value: Option<&str>, account: Option<&Account>) -> Result<(u64, CurrencyCollection)> {
+    match (value, account.and_then(|account| account.balance())) {
+        (None, Some(balance)) => Ok((balance.grams.as_u128() as u64, balance.clone())),
+        _ => decode_balance(value),
+    }
+}
+
+pub fn decode_balance(value: Option<&str>) -> Result<(u64, CurrencyCollection)> {
     let value = value.unwrap_or(DEFAULT_ACCOUNT_BALANCE);
     if let Ok(main) = value.parse::<u64>() {
         Ok((main, CurrencyCollection::with_grams(main)))
@@ -249,11 +451,61 @@ pub fn load_debug_info(filename: &str) -> Option<DbgInfo> {
         .flatten()
 }
 
-pub fn load_config(filename: &str) -> Option<Cell> {
-    let state = load_from_file(filename).unwrap_or_default();
+/// Loads several debug maps into one, e.g. for contracts called in a scenario.
+pub fn load_debug_maps(filenames: &[&str]) -> Result<Option<DbgInfo>> {
+    let mut merged = serde_json::Map::new();
+    for filename in filenames {
+        let file = File::open(filename)
+            .map_err(|e| format_err!("unable to open debug map {}: {}", filename, e))?;
+        match serde_json::from_reader(file)? {
+            Value::Object(map) => merged.extend(map),
+            _ => bail!("debug map {} is not a JSON object", filename),
+        }
+    }
+    if merged.is_empty() {
+        return Ok(None)
+    }
+    Ok(Some(serde_json::from_value(Value::Object(merged))?))
+}
+
+/// Parses config params JSON: either the params object (`{"p0": ..., "p8": ...}`)
+/// or an object with the params under the `config` key.
+pub fn parse_config_json(text: &str) -> Result<Cell> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| format_err!("config is not a valid JSON: {}", e))?;
+    let params = value.get("config").unwrap_or(&value).as_object()
+        .ok_or_else(|| format_err!("config params must be a JSON object"))?;
+    let config_params = ton_block_json::parse_config(params)
+        .map_err(|e| format_err!("cannot parse config params: {}", e))?;
+    config_params.config_params.data().cloned()
+        .ok_or_else(|| format_err!("config params are empty"))
+}
+
+/// Loads config params from the config contract TVC or from JSON file.
+pub fn load_config(filename: &str) -> Result<Cell> {
+    if filename.ends_with(".json") {
+        let text = std::fs::read_to_string(filename)
+            .map_err(|e| format_err!("unable to read config file {}: {}", filename, e))?;
+        return parse_config_json(&text)
+    }
+    let state = load_from_file(filename)
+        .map_err(|e| format_err!("unable to load config contract {}: {}", filename, e))?;
     let (_code, data) = load_code_and_data(&state);
     // config dictionary is located in the first reference of the storage root cell
-    data.into_cell().reference(0).ok()
+    data.into_cell().reference(0)
+        .map_err(|_| format_err!("{} is not a config contract state", filename))
+}
+
+/// Config parameters with the given root at the config contract address -1:5555...
+pub fn config_params(root: Cell) -> ConfigParams {
+    ConfigParams::with_address_and_root(UInt256::from([0x55; 32]), root)
+}
+
+pub fn get_capabilities(config: Option<&Cell>) -> u64 {
+    match config {
+        Some(config_cell) => config_params(config_cell.clone()).capabilities(),
+        None => DEFAULT_CAPABILITIES
+    }
 }
 
 #[derive(PartialEq)]
@@ -263,20 +515,18 @@ pub enum TraceLevel {
     None
 }
 
+/// Resolves source position of the executed instruction.
+pub fn find_position<'a>(info: &EngineTraceInfo, debug_info: &'a Option<DbgInfo>) -> Option<&'a DbgPos> {
+    let cell_hash = info.cmd_code.cell().repr_hash();
+    debug_info.as_ref()?.get(&cell_hash)?.get(&info.cmd_code.pos())
+}
+
 fn get_position(info: &EngineTraceInfo, debug_info: &Option<DbgInfo>) -> Option<String> {
-    if let Some(debug_info) = debug_info {
-        let cell_hash = info.cmd_code.cell().repr_hash();
-        let offset = info.cmd_code.pos();
-        let position = match debug_info.get(&cell_hash) {
-            Some(offset_map) => match offset_map.get(&offset) {
-                Some(pos) => format!("{}:{}", pos.filename, pos.line),
-                None => String::from("-:0 (offset not found)")
-            },
-            None => String::from("-:0 (cell hash not found)")
-        };
-        return Some(position)
-    }
-    None
+    debug_info.as_ref()?;
+    Some(match find_position(info, debug_info) {
+        Some(pos) => format!("{}:{}", pos.filename, pos.line),
+        None => String::from("-:0 (position not found)")
+    })
 }
 
 fn trace_callback_minimal(_engine: &Engine, info: &EngineTraceInfo, debug_info: &Option<DbgInfo>) {
@@ -318,7 +568,117 @@ fn trace_callback(_engine: &Engine, info: &EngineTraceInfo, extended: bool, debu
     println!("----------------------------------------\n");
 }
 
-pub struct TestCallParams<'a, F: Fn(SliceData, bool)> {
+/// Tool observing contract execution step by step, e.g. debugger or profiler.
+pub trait TraceHook: Send {
+    fn on_step(&mut self, engine: &Engine, info: &EngineTraceInfo, position: Option<&DbgPos>);
+}
+
+pub type SharedTraceHook = Arc<Mutex<dyn TraceHook>>;
+
+pub fn create_trace_callback(
+    trace_level: TraceLevel,
+    debug_info: Option<DbgInfo>,
+    hooks: Vec<SharedTraceHook>,
+) -> Option<Arc<TraceCallback>> {
+    if trace_level == TraceLevel::None && hooks.is_empty() {
+        return None
+    }
+    Some(Arc::new(move |engine: &Engine, info: &EngineTraceInfo| {
+        match trace_level {
+            TraceLevel::Full => trace_callback(engine, info, true, &debug_info),
+            TraceLevel::Minimal => trace_callback_minimal(engine, info, &debug_info),
+            TraceLevel::None => {}
+        }
+        if !hooks.is_empty() {
+            let position = find_position(info, &debug_info);
+            for hook in &hooks {
+                hook.lock().unwrap().on_step(engine, info, position);
+            }
+        }
+    }))
+}
+
+/// Tracing every instruction slows the execution down, so the exception is located
+/// only if the source positions or the trace are requested, otherwise only the exit code is described.
+fn locate_exceptions(debug_info: Option<&DbgInfo>, trace_level: &TraceLevel) -> bool {
+    debug_info.is_some() || *trace_level != TraceLevel::None
+}
+
+#[derive(Default, Serialize)]
+pub struct OutActionInfo {
+    pub action: String,
+    pub mode: Option<u8>,
+    /// Outbound message BOC in base64
+    pub message: Option<String>,
+    /// ABI-decoded body of the outbound message
+    pub output: Option<Value>,
+}
+
+#[derive(Default, Serialize)]
+pub struct TestCallResult {
+    pub exit_code: i32,
+    pub success: bool,
+    pub gas_used: i64,
+    /// Gas fee in nanotokens according to the gas prices from config
+    pub gas_fee: u128,
+    pub stack: Vec<String>,
+    pub c4_hash: Option<String>,
+    pub c5_hash: Option<String>,
+    pub actions: Vec<OutActionInfo>,
+    pub outputs: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acceptance: Option<AcceptanceReport>,
+    /// Explanation of the failure exit code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorInfo>,
+    /// Account state after the transaction
+    #[serde(skip)]
+    pub account: Option<Account>,
+}
+
+/// Parses time relative to `now`: an absolute value, `now` or `now` with `+N`/`-N` seconds offset
+/// (`+N` is a shortcut for `now+N`). `unit` is the number of time units in a second.
+pub fn parse_relative_time(value: &str, now: u32, unit: u64) -> Result<u64> {
+    let now = now as u64 * unit;
+    let value = value.trim();
+    let offset = match value.strip_prefix("now") {
+        Some(offset) if offset.is_empty() => return Ok(now),
+        Some(offset) => offset,
+        None if value.starts_with('+') || value.starts_with('-') => value,
+        None => return value.parse::<u64>().map_err(|e| format_err!("invalid time {}: {}", value, e)),
+    };
+    let seconds = offset.parse::<i64>().map_err(|e| format_err!("invalid time offset {}: {}", offset, e))?;
+    let time = now as i128 + seconds as i128 * unit as i128;
+    u64::try_from(time).map_err(|_| format_err!("time {} is out of range", value))
+}
+
+/// FunC-style get-method call: arguments are pushed to the stack followed by the method id.
+pub struct GetMethod {
+    pub id: i64,
+    pub args: Vec<StackItem>,
+}
+
+const CRC16: crc::Crc<u16> = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM);
+
+/// Parses get-method id: a number or a FunC method name.
+pub fn parse_method_id(method: &str) -> i64 {
+    match method.parse::<i64>() {
+        Ok(id) => id,
+        Err(_) => (CRC16.checksum(method.as_bytes()) as i64 & 0xffff) | 0x10000,
+    }
+}
+
+/// Parses get-method arguments from a JSON array of integers (numbers, decimal or hex strings).
+pub fn parse_get_method_args(args: &str) -> Result<Vec<StackItem>> {
+    let args: Vec<Value> = serde_json::from_str(args)
+        .map_err(|e| format_err!("get-method arguments must be a JSON array: {}", e))?;
+    args.iter().map(|arg| {
+        let value = parse_integer(arg).map_err(|e| format_err!("invalid get-method argument {}: {}", arg, e))?;
+        Ok(StackItem::Integer(Arc::new(value)))
+    }).collect()
+}
+
+pub struct TestCallParams<'a, F: Fn(SliceData, bool) -> Option<String>> {
     pub balance: Option<&'a str>,
     pub msg_info: MsgInfo<'a>,
     pub config: Option<Cell>,
@@ -328,22 +688,45 @@ pub struct TestCallParams<'a, F: Fn(SliceData, bool)> {
     pub action_decoder: Option<F>,
     pub trace_level: TraceLevel,
     pub debug_info: Option<DbgInfo>,
-    pub capabilities: u64
+    pub capabilities: u64,
+    pub last_paid: Option<u32>,
+    pub deliver: bool,
+    /// Does not save the states of the contracts updated by the delivered messages
+    pub dry_run: bool,
+    pub json: bool,
+    pub get_method: Option<GetMethod>,
+    /// Get-method run: ABI getter or FunC method, executed without gas limits
+    pub getter: bool,
+    pub check_accept: bool,
+    pub c7: C7Options,
+    /// Library cells available in addition to the account's own libraries
+    pub libraries: HashmapE,
+    pub trace_hooks: Vec<SharedTraceHook>,
+    /// Account to run the contract on instead of the one created from StateInit
+    pub account: Option<Account>,
+    /// The account is uninitialized and the StateInit is attached to the message
+    pub deploy: bool,
+    /// Descriptions of exit codes used to explain failures
+    pub exit_codes: ExitCodes,
+    /// Directory of the contracts the delivered messages are sent to
+    pub accounts_dir: String,
 }
 
 pub fn call_contract<F>(
     addr: MsgAddressInt,
     state_init: StateInit,
     params: TestCallParams<F>,
-) -> Result<(i32, StateInit, bool)>
-    where F: Fn(SliceData, bool)
+) -> Result<(TestCallResult, StateInit)>
+    where F: Fn(SliceData, bool) -> Option<String>
 {
     let func_selector = match params.msg_info.balance {
         Some(_) => 0,
         None => if params.ticktock.is_some() { -2 } else { -1 },
     };
 
-    let msg = create_inbound_msg(func_selector, &params.msg_info, addr.address())?;
+    let dst = MsgAddressInt::with_standart(None, 0, addr.address())
+        .map_err(|e| format_err!("Failed to convert address: {}", e))?;
+    let msg = create_inbound_msg(func_selector, &params.msg_info, dst)?;
 
     if !log_enabled!(Error) {
         init_logger(params.trace_level == TraceLevel::Full)?;
@@ -352,19 +735,42 @@ pub fn call_contract<F>(
     let mut state_init = state_init;
     let (code, data) = load_code_and_data(&state_init);
 
-    let (smc_value, smc_balance) = decode_balance(params.balance)?;
+    let (smc_value, smc_balance) = contract_balance(params.balance, params.account.as_ref())?;
     let registers = initialize_registers(
         data,
         code.clone().into_cell(),
         addr.clone(),
         params.msg_info.now,
         smc_balance,
-        params.config,
-        params.capabilities
+        Network { config_params: params.config.clone(), capabilities: params.capabilities },
+        &params.c7,
     )?;
 
+    let msg_value = if func_selector == 0 {
+        decode_balance(params.msg_info.balance)?.0 // for internal message
+    } else {
+        0 // for external message
+    };
+    let config = blockchain_config(params.config, params.capabilities)?;
+    let gas_config = config.get_gas_config(addr.is_masterchain());
+    let gas = if let Some(gas_limit) = params.gas_limit {
+        let mut tmp_gas = Gas::test();
+        tmp_gas.new_gas_limit(gas_limit);
+        tmp_gas
+    } else if params.getter || params.get_method.is_some() {
+        Gas::test()
+    } else {
+        let is_special = config.is_special_account(&addr)?;
+        init_gas(smc_value as u128, msg_value as u128, func_selector == -1, is_special, func_selector != -2, gas_config)
+    };
+
     let mut stack = Stack::new();
-    if func_selector > -2 {
+    if let Some(get_method) = params.get_method {
+        for arg in get_method.args {
+            stack.push(arg);
+        }
+        stack.push(int!(get_method.id));
+    } else if func_selector > -2 {
         let msg_cell = StackItem::Cell(
             msg.ok_or_else(|| format_err!("Failed to create message"))?.serialize()?
         );
@@ -380,12 +786,6 @@ pub fn call_contract<F>(
             }
         }
 
-        let msg_value = if func_selector == 0 {
-            decode_balance(params.msg_info.balance)?.0 // for internal message
-        } else {
-            0 // for external message
-        };
-
         stack
             .push(int!(smc_value))        // contract balance
             .push(int!(msg_value))        // msg value
@@ -402,33 +802,40 @@ pub fn call_contract<F>(
             .push(int!(func_selector));
     }
 
-    let gas = if let Some(gas_limit) = params.gas_limit {
-        let mut tmp_gas = Gas::test();
-        tmp_gas.new_gas_limit(gas_limit);
-        tmp_gas
-    } else {
-        Gas::test()
-    };
-
     let library_map = HashmapE::with_hashmap(256, state_init.library.root().cloned());
 
-    println!("Engine capabilities: {}", params.capabilities);
+    let verbose = !params.json;
+    if verbose {
+        println!("Engine capabilities: {}", capabilities_printer(params.capabilities));
+        println!("Gas limit: {}, credit: {}, max: {}", gas.get_gas_limit(), gas.get_gas_credit(), gas.get_gas_limit_max());
+    }
+    let mut trace_hooks = params.trace_hooks;
+    let locator = Arc::new(Mutex::new(ExceptionLocator::default()));
+    if locate_exceptions(params.debug_info.as_ref(), &params.trace_level) {
+        trace_hooks.push(locator.clone());
+    }
+    let acceptance = if params.check_accept && func_selector == -1 {
+        let checker = Arc::new(Mutex::new(AcceptanceChecker::new(gas.get_gas_credit())));
+        trace_hooks.push(checker.clone());
+        Some(checker)
+    } else {
+        None
+    };
     let mut engine = Engine::with_capabilities(
         params.capabilities
     ).setup_with_libraries(
-        code, Some(registers), Some(stack), Some(gas), vec!(library_map)
+        code, Some(registers), Some(stack), Some(gas), vec!(library_map, params.libraries)
     );
     engine.set_trace(0);
-    let debug_info = params.debug_info;
-    match params.trace_level {
-        TraceLevel::Full => engine.set_trace_callback(move |engine, info| { trace_callback(engine, info, true, &debug_info); }),
-        TraceLevel::Minimal => engine.set_trace_callback(move |engine, info| { trace_callback_minimal(engine, info, &debug_info); }),
-        TraceLevel::None => {}
+    if let Some(callback) = create_trace_callback(params.trace_level, params.debug_info, trace_hooks) {
+        engine.set_trace_callback(move |engine, info| callback(engine, info));
     }
     let exit_code = match engine.execute() {
         Err(exc) => match tvm_exception(exc) {
             Ok(exc) => {
-                println!("Unhandled exception: {}", exc);
+                if verbose {
+                    println!("Unhandled exception: {}", exc);
+                }
                 exc.exception_or_custom_code()
             }
             _ => -1
@@ -437,30 +844,266 @@ pub fn call_contract<F>(
     };
 
     let is_vm_success = engine.get_committed_state().is_committed();
-    println!("TVM terminated with exit code {}", exit_code);
-    println!("Computing phase is success: {}", is_vm_success);
-    println!("Gas used: {}", engine.get_gas().get_gas_used());
-    println!();
-    println!("{}", engine.dump_stack("Post-execution stack state", false));
-    println!("{}", engine.dump_ctrls(false));
+    let mut result = TestCallResult {
+        exit_code,
+        success: is_vm_success,
+        gas_used: engine.get_gas().get_gas_used(),
+        gas_fee: gas_config.calc_gas_fee(engine.get_gas().get_gas_used() as u64),
+        stack: engine.stack().iter().map(ToString::to_string).collect(),
+        acceptance: acceptance.map(|checker| checker.lock().unwrap().report(engine.get_gas().get_gas_credit() == 0)),
+        error: locator.lock().unwrap().error_info(exit_code, &params.exit_codes),
+        ..Default::default()
+    };
+    if verbose {
+        println!("TVM terminated with exit code {}", exit_code);
+        if let Some(error) = &result.error {
+            print!("{}", error_printer(exit_code, error));
+        }
+        println!("Computing phase is success: {}", is_vm_success);
+        println!("Gas used: {}", result.gas_used);
+        println!("Gas fee: {}", result.gas_fee);
+        if let Some(acceptance) = &result.acceptance {
+            print!("{}", acceptance_printer(acceptance));
+        }
+        println!();
+        println!("{}", engine.dump_stack("Post-execution stack state", false));
+        println!("{}", engine.dump_ctrls(false));
+    }
 
     if is_vm_success {
-        if let Some(decoder) = params.action_decoder {
-            decode_actions(engine.get_actions(), &mut state_init, decoder)?;
+        let actions = engine.get_actions();
+        if let StackItem::Cell(cell) = &actions {
+            result.c5_hash = Some(cell.repr_hash().to_hex_string());
+        }
+        if params.action_decoder.is_some() || params.json {
+            decode_actions(actions, &mut state_init, params.action_decoder.as_ref(), &mut result, verbose)?;
         }
 
         state_init.data = match engine.get_committed_state().get_root() {
             StackItem::Cell(root_cell) => Some(root_cell.clone()),
             _ => panic!("cannot get root data: c4 register is not a cell."),
         };
+        result.c4_hash = state_init.data.as_ref().map(|data| data.repr_hash().to_hex_string());
     }
 
-    Ok((exit_code, state_init, is_vm_success))
+    Ok((result, state_init))
+}
+
+/// Computes gas limits of the compute phase the same way as the validators do:
+/// the limit is bought by the message value (or the account balance for external
+/// messages which get gas credit instead), the maximum is bought by the account balance.
+pub fn init_gas(
+    acc_balance: u128,
+    msg_value: u128,
+    is_external: bool,
+    is_special: bool,
+    is_ordinary: bool,
+    gas_config: &GasLimitsPrices,
+) -> Gas {
+    let gas_max = if is_special {
+        gas_config.special_gas_limit
+    } else {
+        std::cmp::min(gas_config.gas_limit, gas_config.calc_gas(acc_balance))
+    };
+    let mut gas_credit = 0;
+    let gas_limit = if !is_ordinary {
+        gas_max
+    } else {
+        if is_external {
+            gas_credit = std::cmp::min(gas_config.gas_credit, gas_max);
+        }
+        std::cmp::min(gas_max, gas_config.calc_gas(msg_value))
+    };
+    Gas::new(gas_limit as i64, gas_credit as i64, gas_max as i64, gas_config.get_real_gas_price() as i64)
+}
+
+pub fn blockchain_config(config: Option<Cell>, capabilities: u64) -> Result<BlockchainConfig> {
+    let mut config_params = match config {
+        Some(config_cell) => config_params(config_cell),
+        None => BlockchainConfig::default().raw_config().clone(),
+    };
+    let mut global_version = config_params.get_global_version()?;
+    global_version.capabilities = capabilities;
+    config_params.set_config(ConfigParamEnum::ConfigParam8(ConfigParam8 { global_version }))?;
+    BlockchainConfig::with_config(config_params)
+}
+
+/// Creates inbound message for the transaction, internal if message value is supplied,
+/// the body of external message is signed if the key file is supplied.
+pub fn create_message(addr: &MsgAddressInt, msg_info: &MsgInfo, key_file: Option<Option<&str>>) -> Result<Message> {
+    let func_selector = if msg_info.balance.is_some() { 0 } else { -1 };
+    let mut body = msg_info.body.clone();
+    if func_selector == -1 {
+        if let Some(key_file) = key_file {
+            let mut signed_body = body.unwrap_or_default();
+            sign_body(&mut signed_body, key_file)?;
+            body = Some(signed_body);
+        }
+    }
+    let msg_info = MsgInfo { body, ..*msg_info };
+    create_inbound_msg(func_selector, &msg_info, addr.clone())?
+        .ok_or_else(|| format_err!("Failed to create message"))
+}
+
+pub fn execute_transaction<F>(
+    addr: MsgAddressInt,
+    state_init: StateInit,
+    params: TestCallParams<F>,
+) -> Result<(TestCallResult, StateInit)>
+    where F: Fn(SliceData, bool) -> Option<String>
+{
+    let mut msg = create_message(&addr, &params.msg_info, params.key_file)?;
+
+    if !log_enabled!(Error) {
+        init_logger(params.trace_level == TraceLevel::Full)?;
+    }
+
+    let verbose = !params.json;
+    let (_, balance) = decode_balance(params.balance)?;
+    let mut deploy_error = None;
+    let account = match params.account {
+        _ if params.deploy => {
+            let hash = state_init.hash()?.to_hex_string();
+            if addr.address().to_hex_string() != hash {
+                if verbose {
+                    println!("WARNING: address {} does not match the StateInit hash {}, the account will not be initialized", addr, hash);
+                }
+                deploy_error = Some(ErrorInfo {
+                    description: Some(format!("address {} does not match the StateInit hash {}", addr, hash)),
+                    ..Default::default()
+                });
+            }
+            msg.set_state_init(state_init.clone());
+            let last_paid = params.last_paid.unwrap_or(params.msg_info.now);
+            Account::uninit(addr.clone(), 0, last_paid, balance.clone())
+        }
+        Some(mut account) => {
+            if params.balance.is_some() {
+                account.set_balance(balance.clone());
+            }
+            account
+        }
+        None => {
+            let last_paid = params.last_paid.unwrap_or(params.msg_info.now);
+            Account::active_by_init_code_hash(addr.clone(), balance.clone(), last_paid, state_init.clone(), false)?
+        }
+    };
+
+    if verbose {
+        println!("Engine capabilities: {}", capabilities_printer(params.capabilities));
+    }
+    let config = blockchain_config(params.config, params.capabilities)?;
+    let mut sandbox = Sandbox::new(&params.accounts_dir, config, balance, params.msg_info.now);
+    let mut trace_hooks = params.trace_hooks;
+    // delivered messages would overwrite the location of the exception
+    let locator = if params.deliver {
+        None
+    } else {
+        let locator = Arc::new(Mutex::new(ExceptionLocator::default()));
+        if locate_exceptions(params.debug_info.as_ref(), &params.trace_level) {
+            trace_hooks.push(locator.clone());
+        }
+        Some(locator)
+    };
+    sandbox.set_trace_callback(create_trace_callback(params.trace_level, params.debug_info, trace_hooks));
+    sandbox.set_libraries(params.libraries);
+    sandbox.insert_account(account)?;
+
+    let transactions = match sandbox.execute(msg, params.deliver) {
+        Ok(transactions) => transactions,
+        Err(err) => {
+            if let Some(ExecutorError::NoAcceptError(exit_code, _)) = err.downcast_ref::<ExecutorError>() {
+                let error = deploy_error.or_else(|| {
+                    locator.as_ref().and_then(|locator| locator.lock().unwrap().error_info(*exit_code, &params.exit_codes))
+                });
+                if verbose {
+                    println!("External message was not accepted by the contract");
+                    println!("TVM terminated with exit code {}", exit_code);
+                    if let Some(error) = &error {
+                        print!("{}", error_printer(*exit_code, error));
+                    }
+                }
+                let result = TestCallResult { exit_code: *exit_code, error, ..Default::default() };
+                return Ok((result, state_init))
+            }
+            return Err(err)
+        }
+    };
+
+    let mut result = TestCallResult::default();
+    for (i, (dst, transaction)) in transactions.iter().enumerate() {
+        if verbose {
+            if params.deliver {
+                println!("Account {}:", dst);
+            }
+            println!("{}", transaction_printer(transaction)?);
+        }
+        if i == 0 && (params.action_decoder.is_some() || params.json) {
+            if verbose {
+                println!("Output messages:\n----------------");
+            }
+            let decoder = params.action_decoder.as_ref();
+            transaction.iterate_out_msgs(|msg| {
+                out_action_info("SendMsg", None, Some(&msg), decoder, &mut result, verbose)?;
+                Ok(true)
+            })?;
+        }
+    }
+
+    let transaction = &transactions[0].1;
+    let (exit_code, is_success) = transaction_result(transaction)?;
+    result.exit_code = exit_code;
+    result.success = is_success;
+    if let TransactionDescr::Ordinary(descr) = transaction.read_description()? {
+        if let TrComputePhase::Vm(vm) = descr.compute_ph {
+            result.gas_used = vm.gas_used.as_u64() as i64;
+            result.gas_fee = vm.gas_fees.as_u128();
+            result.error = locator.as_ref().and_then(|locator| locator.lock().unwrap().error_info(exit_code, &params.exit_codes));
+        }
+    }
+    if !is_success && deploy_error.is_some() {
+        result.error = deploy_error;
+    }
+    if verbose {
+        if let Some(error) = &result.error {
+            print!("{}", error_printer(exit_code, error));
+        }
+    }
+    let account = sandbox.account(&addr)?;
+    if verbose {
+        println!("{}", account_printer(account));
+    }
+    result.c4_hash = account.get_data().map(|data| data.repr_hash().to_hex_string());
+    let state_init = account.state_init().cloned().unwrap_or(state_init);
+    result.account = Some(account.clone());
+    if params.deliver && !params.dry_run {
+        for saved in sandbox.save_accounts(Some(&addr))? {
+            if verbose {
+                println!("Contract {} persistent data updated", saved);
+            }
+        }
+    }
+    Ok((result, state_init))
+}
+
+/// Returns compute phase exit code and success flag of the transaction.
+pub fn transaction_result(transaction: &Transaction) -> Result<(i32, bool)> {
+    Ok(match transaction.read_description()? {
+        TransactionDescr::Ordinary(descr) => {
+            let exit_code = match descr.compute_ph {
+                TrComputePhase::Vm(vm) => vm.exit_code,
+                TrComputePhase::Skipped(_) => -1,
+            };
+            (exit_code, !descr.aborted)
+        }
+        _ => (-1, false)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ton_block::AccountStatus;
 
     fn create_inbound_body(a: i32, b: i32, func_id: i32) -> Result<Cell> {
         let mut builder = BuilderData::new();
@@ -472,6 +1115,131 @@ mod tests {
         builder.into_cell()
     }
 
+    const NOW: u32 = 1_700_000_000;
+    const TON: u64 = 1_000_000_000;
+
+    type Decoder = fn(SliceData, bool) -> Option<String>;
+
+    fn transaction_params(value: &str) -> TestCallParams<'_, Decoder> {
+        // the logger is initialized by the first call only
+        static LOGGER: std::sync::Once = std::sync::Once::new();
+        LOGGER.call_once(|| { let _ = init_logger(false); });
+        TestCallParams {
+            balance: Some("10000000000"),
+            msg_info: MsgInfo { balance: Some(value), src: None, now: NOW, bounced: false, body: None },
+            config: None,
+            key_file: None,
+            ticktock: None,
+            gas_limit: None,
+            action_decoder: None,
+            trace_level: TraceLevel::None,
+            debug_info: None,
+            capabilities: DEFAULT_CAPABILITIES,
+            last_paid: None,
+            deliver: false,
+            dry_run: true,
+            json: true,
+            get_method: None,
+            getter: false,
+            check_accept: false,
+            c7: C7Options::default(),
+            libraries: HashmapE::with_bit_len(256),
+            trace_hooks: vec![],
+            account: None,
+            deploy: false,
+            exit_codes: ExitCodes::default(),
+            accounts_dir: ".".to_string(),
+        }
+    }
+
+    /// Contract sending the messages stored in its data with mode 1.
+    fn sender_state(messages: &[Message]) -> StateInit {
+        let code = format!("PUSHROOT\nCTOS\n{}ENDS\n", "LDREF\nSWAP\nPUSHINT 1\nSENDRAWMSG\n".repeat(messages.len()));
+        let mut data = BuilderData::new();
+        for msg in messages {
+            data.checked_append_reference(msg.serialize().unwrap()).unwrap();
+        }
+        StateInit {
+            code: Some(ton_labs_assembler::compile_code(&code).unwrap().into_cell()),
+            data: Some(data.into_cell().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    fn out_messages(result: &TestCallResult) -> Vec<Message> {
+        result.actions.iter()
+            .map(|action| Message::construct_from_base64(action.message.as_ref().unwrap()).unwrap())
+            .collect()
+    }
+
+    fn grams(result: &TestCallResult) -> u128 {
+        result.account.as_ref().unwrap().balance().unwrap().grams.as_u128()
+    }
+
+    #[test]
+    fn test_execute_transaction() {
+        let addr = MsgAddressInt::with_standart(None, 0, [1; 32].into()).unwrap();
+        let dst = MsgAddressInt::with_standart(None, 0, [2; 32].into()).unwrap();
+        let out_msg = Message::with_int_header(InternalMessageHeader {
+            ihr_disabled: true,
+            dst: dst.clone(),
+            value: CurrencyCollection::with_grams(TON),
+            ..Default::default()
+        });
+        let (result, _) = execute_transaction(addr.clone(), sender_state(&[out_msg]), transaction_params("1000000000")).unwrap();
+        assert_eq!((result.exit_code, result.success), (0, true));
+        assert!(result.gas_used > 0 && result.gas_fee > 0);
+        let messages = out_messages(&result);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].dst(), Some(dst));
+        assert_eq!(messages[0].src(), Some(addr.clone()));
+        assert_eq!(messages[0].get_value().unwrap().grams.as_u128(), TON as u128);
+        // 1 token is received and sent, the fees are paid from the balance
+        let balance = grams(&result);
+        assert!(balance < 10 * TON as u128 - result.gas_fee && balance > 9 * TON as u128);
+
+        // the failed compute phase bounces the value without fees charged from the balance
+        let state_init = StateInit {
+            code: Some(ton_labs_assembler::compile_code("THROW 101").unwrap().into_cell()),
+            data: Some(Cell::default()),
+            ..Default::default()
+        };
+        let (result, _) = execute_transaction(addr, state_init, transaction_params("1000000000")).unwrap();
+        assert_eq!((result.exit_code, result.success), (101, false));
+        let messages = out_messages(&result);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].int_header().unwrap().bounced);
+        assert!(messages[0].get_value().unwrap().grams.as_u128() < TON as u128);
+        assert_eq!(grams(&result), 10 * TON as u128);
+    }
+
+    #[test]
+    fn test_deploy() {
+        let state_init = sender_state(&[]);
+        let addr = MsgAddressInt::with_standart(None, 0, state_init.hash().unwrap().into()).unwrap();
+        let params = TestCallParams { deploy: true, ..transaction_params("1000000000") };
+        let (result, new_state) = execute_transaction(addr, state_init.clone(), params).unwrap();
+        assert_eq!((result.exit_code, result.success), (0, true));
+        assert!(result.error.is_none());
+        let account = result.account.unwrap();
+        assert_eq!(account.status(), AccountStatus::AccStateActive);
+        assert_eq!(account.get_code(), state_init.code);
+        assert_eq!(new_state.code, state_init.code);
+    }
+
+    #[test]
+    fn test_deploy_address_mismatch() {
+        let addr = MsgAddressInt::with_standart(None, 0, [1; 32].into()).unwrap();
+        let params = TestCallParams { deploy: true, ..transaction_params("1000000000") };
+        let (result, _) = execute_transaction(addr, sender_state(&[]), params).unwrap();
+        assert!(!result.success);
+        let description = result.error.unwrap().description.unwrap();
+        assert!(description.contains("does not match the StateInit hash"), "{}", description);
+        if let Some(account) = result.account {
+            assert_ne!(account.status(), AccountStatus::AccStateActive);
+        }
+    }
+
     #[test]
     fn test_msg_print() {
         let msg = create_external_inbound_msg(
@@ -496,6 +1264,128 @@ mod tests {
         println!("SendMsg action:\n{}", msg_printer(&msg).unwrap_or("Undefined".to_string()));
     }
 
+    #[test]
+    fn test_result_json() {
+        let result = TestCallResult {
+            exit_code: 0,
+            success: true,
+            gas_used: 1234,
+            actions: vec![OutActionInfo { action: "SetCode".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["exit_code"], 0);
+        assert_eq!(json["success"], true);
+        assert_eq!(json["gas_used"], 1234);
+        assert_eq!(json["actions"][0]["action"], "SetCode");
+        assert!(json["c4_hash"].is_null());
+    }
+
+    #[test]
+    fn test_init_gas() {
+        let config = BlockchainConfig::default();
+        let gas_config = config.get_gas_config(false);
+        let gas = init_gas(1_000_000_000_000, 0, true, false, true, gas_config);
+        assert_eq!(gas.get_gas_credit(), gas_config.gas_credit as i64);
+        assert_eq!(gas.get_gas_limit_max(), gas_config.gas_limit as i64);
+        let gas = init_gas(1_000_000_000_000, 1_000_000_000_000, false, false, true, gas_config);
+        assert_eq!(gas.get_gas_credit(), 0);
+        assert_eq!(gas.get_gas_limit(), gas.get_gas_limit_max());
+        let gas = init_gas(1_000_000_000_000, 0, false, true, false, gas_config);
+        assert_eq!(gas.get_gas_limit(), gas_config.special_gas_limit as i64);
+    }
+
+    #[test]
+    fn test_json_to_stack_item() {
+        let item = json_to_stack_item(&serde_json::json!([1, "-0x10", null, [2]])).unwrap();
+        assert_eq!(item, StackItem::tuple(vec![int!(1), int!(-16), StackItem::None, StackItem::tuple(vec![int!(2)])]));
+        assert!(json_to_stack_item(&serde_json::json!({"builder": ""})).is_err());
+        assert_eq!(parse_uint256("0x1").unwrap(), UInt256::from_str(&format!("{:0>64}", 1)).unwrap());
+    }
+
+    #[test]
+    fn test_prev_blocks_info_index() {
+        let registers = |items: usize| {
+            let c7 = C7Options {
+                prev_blocks_info: Some(serde_json::json!([[1]])),
+                tuple: Some(vec![serde_json::json!(0); items]),
+                ..Default::default()
+            };
+            initialize_registers(
+                SliceData::default(),
+                Cell::default(),
+                MsgAddressInt::default(),
+                0,
+                CurrencyCollection::default(),
+                Network { config_params: None, capabilities: 0 },
+                &c7,
+            )
+        };
+        assert!(registers(PREV_BLOCKS_INFO_INDEX - 1).is_err());
+        for items in [PREV_BLOCKS_INFO_INDEX, PREV_BLOCKS_INFO_INDEX + 2] {
+            let c7 = registers(items).unwrap().get(7).unwrap().as_tuple().unwrap()[0].as_tuple().unwrap().to_vec();
+            assert_eq!(c7.len(), items.max(PREV_BLOCKS_INFO_INDEX + 1));
+            assert_eq!(c7[PREV_BLOCKS_INFO_INDEX], StackItem::tuple(vec![StackItem::tuple(vec![int!(1)])]));
+            assert_eq!(c7[PREV_BLOCKS_INFO_INDEX - 1], int!(0));
+        }
+    }
+
+    #[test]
+    fn test_parse_config_json() {
+        let params = r#"{"p8": {"version": 44, "capabilities": "2315376366"}}"#;
+        let config = parse_config_json(params).unwrap();
+        assert_eq!(get_capabilities(Some(&config)), 0x8a01d6ee);
+        let nested = parse_config_json(&format!(r#"{{"config": {}}}"#, params)).unwrap();
+        assert_eq!(nested, config);
+        assert!(parse_config_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_bounced_body() {
+        let bounce = GlobalCapabilities::CapBounceMsgBody as u64;
+        let full_body = GlobalCapabilities::CapFullBodyInBounced as u64;
+        let original = SliceData::load_cell(create_inbound_body(1, 2, 0x1234).unwrap()).unwrap();
+        assert!(bounced_body(Some(original.clone()), 0).unwrap().is_none());
+        assert!(bounced_body(Some(original.clone()), full_body).unwrap().is_none());
+
+        let mut body = bounced_body(Some(original.clone()), bounce).unwrap().unwrap();
+        assert_eq!(body.remaining_bits(), 32 + 8 + 32 + 32 + 32);
+        assert_eq!(body.remaining_references(), 0);
+        assert_eq!(body.get_next_u32().unwrap(), 0xFFFF_FFFF);
+        assert_eq!(body.get_next_byte().unwrap(), 0);
+        assert_eq!(body.get_next_u32().unwrap(), 0x1234);
+
+        let mut long = BuilderData::new();
+        long.append_raw(&[0xAB; 64], 512).unwrap();
+        let long = SliceData::load_builder(long).unwrap();
+        let body = bounced_body(Some(long.clone()), bounce).unwrap().unwrap();
+        assert_eq!(body.remaining_bits(), 32 + 256);
+        assert_eq!(body.remaining_references(), 0);
+        let body = bounced_body(Some(long.clone()), bounce | full_body).unwrap().unwrap();
+        assert_eq!(body.remaining_bits(), 32 + 256);
+        assert_eq!(body.reference(0).unwrap(), long.into_cell());
+        assert_eq!(bounced_body(None, bounce | full_body).unwrap().unwrap().remaining_bits(), 32);
+    }
+
+    #[test]
+    fn test_parse_relative_time() {
+        assert_eq!(parse_relative_time("1700000000", 100, 1).unwrap(), 1700000000);
+        assert_eq!(parse_relative_time("now", 100, 1000).unwrap(), 100000);
+        assert_eq!(parse_relative_time("now+60", 100, 1).unwrap(), 160);
+        assert_eq!(parse_relative_time("-30", 100, 1000).unwrap(), 70000);
+        assert!(parse_relative_time("-200", 100, 1).is_err());
+        assert!(parse_relative_time("soon", 100, 1).is_err());
+    }
+
+    #[test]
+    fn test_get_method_id() {
+        assert_eq!(parse_method_id("seqno"), 85143);
+        assert_eq!(parse_method_id("123"), 123);
+        let args = parse_get_method_args(r#"[1, "2", "0x10"]"#).unwrap();
+        assert_eq!(args, vec![int!(1), int!(2), int!(16)]);
+        assert!(parse_get_method_args(r#"{"a": 1}"#).is_err());
+    }
+
     #[test]
     fn test_decode_balance() {
         let (main, balance) = decode_balance(Some(r#"{ "main": 100, "extra": {"0": 33, "50": 99} }"#)).unwrap();